@@ -0,0 +1,106 @@
+//! Physics benchmarks: gravity (and both gravity kernels), collision impulses and full updates
+//! on seeded random layouts of 100, 1k and 10k planets
+//!
+//! cargo bench (add --features parallel to compare)
+
+#[macro_use]
+extern crate criterion;
+extern crate galaxy;
+
+use criterion::{BatchSize, BenchmarkId, Criterion};
+
+use galaxy::GalaxyController;
+use galaxy::config::Config;
+use galaxy::barnes_hut::QuadTree;
+use galaxy::gravity_kernel::PlanetArrays;
+
+/// number of planets
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+/// same seed, same layout
+const SEED: u64 = 42;
+
+/// controller with count random planets, the scene grows with the number of planets (same density)
+fn controller(count: usize) -> (GalaxyController, Config) {
+    let mut config = Config::default();
+    config.seed = Some(SEED);
+    config.random_planet_count = count;
+    config.random_scene_radius = 10. * config.planet_radius * (count as f64).sqrt();
+    (GalaxyController::from_config(&config), config)
+}
+
+fn bench_gravitational_interactions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_gravitational_interactions");
+    group.sample_size(10);
+    for count in SIZES.iter() {
+        let (mut controller, config) = controller(*count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), count, |b, _| b.iter(|| {
+            controller.compute_accelerations();
+            controller.compute_gravitational_interactions(config.frame_time_step);
+        }));
+    }
+    group.finish();
+}
+
+/// accelerations of every planet: per-planet loop (array of structs) vs vectorised kernel (structure of arrays)
+/// vs Barnes–Hut (tree built every iteration, default theta)
+fn bench_gravity_kernel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gravity_kernel");
+    group.sample_size(10);
+    for count in SIZES.iter() {
+        let (controller, config) = controller(*count);
+        let planets = &controller.galaxy.planets;
+        let (gravity, softening_kernel, softening_factor) = (config.gravity, config.softening_kernel, config.softening_factor);
+        group.bench_with_input(BenchmarkId::new("reference", count), count, |b, _| b.iter(|| {
+            planets.iter().map(|planet| GalaxyController::compute_gravitational_force(planet, planets, gravity, softening_kernel, softening_factor)).collect::<Vec<_>>()
+        }));
+        let mut arrays = PlanetArrays::new();
+        group.bench_with_input(BenchmarkId::new("soa", count), count, |b, _| b.iter(|| {
+            arrays.update(planets);
+            planets.iter().map(|planet| arrays.acceleration(planet.position, gravity, softening_kernel, softening_factor)).collect::<Vec<_>>()
+        }));
+        let mut tree = QuadTree::new();
+        group.bench_with_input(BenchmarkId::new("barnes_hut", count), count, |b, _| b.iter(|| {
+            arrays.update(planets);
+            tree.update(&arrays);
+            planets.iter().map(|planet| tree.acceleration(planet.position, config.barnes_hut_theta, gravity, softening_kernel, softening_factor)).collect::<Vec<_>>()
+        }));
+    }
+    group.finish();
+}
+
+fn bench_impulses(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_impulses");
+    group.sample_size(10);
+    for count in SIZES.iter() {
+        let (mut controller, _) = controller(*count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), count, |b, _| b.iter(|| {
+            // contacts are only cleared by update
+            controller.contacts.clear();
+            controller.compute_impulses();
+        }));
+    }
+    group.finish();
+}
+
+fn bench_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    group.sample_size(10);
+    for count in SIZES.iter() {
+        // every iteration starts from the initial planets: the galaxy does not collapse over the run,
+        // so substeps and contacts stay comparable across samples
+        let (mut controller, config) = controller(*count);
+        let planets = controller.galaxy.planets.clone();
+        group.bench_with_input(BenchmarkId::from_parameter(count), count, |b, _| b.iter_batched(
+            || planets.clone(),
+            |planets| {
+                controller.galaxy.planets = planets;
+                controller.update(config.frame_time_step);
+            },
+            BatchSize::LargeInput,
+        ));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gravitational_interactions, bench_gravity_kernel, bench_impulses, bench_update);
+criterion_main!(benches);
@@ -0,0 +1,238 @@
+//! Barnes–Hut gravity: a quadtree groups planets, distant groups pull as a single mass at their center of mass
+//! O(n log n) per step instead of O(n^2), for galaxies of thousands of planets (see `GravitySolver::BarnesHut`)
+
+use graphics::math::{Scalar, Vec2d};
+
+use crate::gravity_kernel::PlanetArrays;
+use crate::softening::SofteningKernel;
+
+/// planets per leaf cell at most, leaf planets are summed directly
+const LEAF_SIZE: usize = 8;
+/// cells this deep are leaves whatever their planet count (e.g. coincident planets)
+const MAX_DEPTH: usize = 40;
+/// cells pending during a walk at most: each level adds at most 3 cells to the stack
+const STACK_SIZE: usize = 3 * MAX_DEPTH + 4;
+
+/// quadtree cell
+struct Cell {
+    /// center of the cell square
+    center: Vec2d<Scalar>,
+    /// half the side of the cell square
+    half_size: Scalar,
+    /// total mass of the cell planets
+    mass: Scalar,
+    /// center of mass of the cell planets
+    center_of_mass: Vec2d<Scalar>,
+    /// cell planets: start..end in tree order
+    planets: (usize, usize),
+    /// child cells: start..end in `QuadTree::children`, empty for leaves
+    children: (usize, usize),
+}
+
+/// Quadtree over planet positions and masses, rebuilt before each force computation
+pub struct QuadTree {
+    /// planets sorted so that the planets of every cell are contiguous
+    planets: PlanetArrays,
+    /// planet indices (`PlanetArrays` order) in tree order
+    order: Vec<usize>,
+    /// cells, the root is the first one
+    cells: Vec<Cell>,
+    /// child cell indices
+    children: Vec<usize>,
+}
+
+impl QuadTree {
+    /// Creates an empty quadtree.
+    pub fn new() -> QuadTree {
+        QuadTree {
+            planets: PlanetArrays::new(),
+            order: vec![],
+            cells: vec![],
+            children: vec![],
+        }
+    }
+
+    /// Rebuilds the tree from planet positions and masses, allocations are reused.
+    pub fn update(&mut self, arrays: &PlanetArrays) {
+        self.cells.clear();
+        self.children.clear();
+        self.order.clear();
+        self.order.extend(0..arrays.x.len());
+        if self.order.is_empty() {
+            self.planets.update(&[]);
+            return;
+        }
+        let min_x = arrays.x.iter().cloned().fold(Scalar::INFINITY, Scalar::min);
+        let max_x = arrays.x.iter().cloned().fold(Scalar::NEG_INFINITY, Scalar::max);
+        let min_y = arrays.y.iter().cloned().fold(Scalar::INFINITY, Scalar::min);
+        let max_y = arrays.y.iter().cloned().fold(Scalar::NEG_INFINITY, Scalar::max);
+        let center = [(min_x + max_x) / 2., (min_y + max_y) / 2.];
+        let half_size = ((max_x - min_x).max(max_y - min_y) / 2.).max(Scalar::MIN_POSITIVE);
+        let mut order = std::mem::take(&mut self.order);
+        self.build(arrays, &mut order, 0, center, half_size, 0);
+        self.order = order;
+        let planets = &mut self.planets;
+        planets.x.clear();
+        planets.y.clear();
+        planets.mass.clear();
+        planets.x.extend(self.order.iter().map(|i| arrays.x[*i]));
+        planets.y.extend(self.order.iter().map(|i| arrays.y[*i]));
+        planets.mass.extend(self.order.iter().map(|i| arrays.mass[*i]));
+    }
+
+    /// builds the cell of order[..] (tree order offset start), returns its index
+    fn build(&mut self, arrays: &PlanetArrays, order: &mut [usize], start: usize, center: Vec2d<Scalar>, half_size: Scalar, depth: usize) -> usize {
+        let mass: Scalar = order.iter().map(|i| arrays.mass[*i]).sum();
+        let weighted = order.iter().fold([0., 0.], |acc, i| [acc[0] + arrays.x[*i] * arrays.mass[*i], acc[1] + arrays.y[*i] * arrays.mass[*i]]);
+        let index = self.cells.len();
+        self.cells.push(Cell {
+            center,
+            half_size,
+            mass,
+            center_of_mass: [weighted[0] / mass, weighted[1] / mass],
+            planets: (start, start + order.len()),
+            children: (0, 0),
+        });
+        if order.len() <= LEAF_SIZE || depth >= MAX_DEPTH {
+            return index;
+        }
+        // quadrants: left/right of the center, then bottom/top
+        let left = partition(order, |i| arrays.x[i] < center[0]);
+        let (left_order, right_order) = order.split_at_mut(left);
+        let bottom_left = partition(left_order, |i| arrays.y[i] < center[1]);
+        let bottom_right = partition(right_order, |i| arrays.y[i] < center[1]);
+        let quarter = half_size / 2.;
+        let (left_bottom, left_top) = left_order.split_at_mut(bottom_left);
+        let (right_bottom, right_top) = right_order.split_at_mut(bottom_right);
+        let quadrants = [
+            (left_bottom, [center[0] - quarter, center[1] - quarter], start),
+            (left_top, [center[0] - quarter, center[1] + quarter], start + bottom_left),
+            (right_bottom, [center[0] + quarter, center[1] - quarter], start + left),
+            (right_top, [center[0] + quarter, center[1] + quarter], start + left + bottom_right),
+        ];
+        let mut children = vec![];
+        for (quadrant, quadrant_center, quadrant_start) in quadrants {
+            if !quadrant.is_empty() {
+                children.push(self.build(arrays, quadrant, quadrant_start, quadrant_center, quarter, depth + 1));
+            }
+        }
+        let first = self.children.len();
+        self.children.extend(children);
+        self.cells[index].children = (first, self.children.len());
+        index
+    }
+
+    /// Gravitational acceleration at position (same force as `PlanetArrays::acceleration`):
+    /// cells seen from position under an angle smaller than theta (size / distance) pull from their center of mass,
+    /// other cells are opened. theta = 0: every planet is summed directly.
+    pub fn acceleration(&self, position: Vec2d<Scalar>, theta: Scalar, gravity: Scalar, softening_kernel: SofteningKernel, softening_factor: Scalar) -> Vec2d<Scalar> {
+        let acceleration = match softening_kernel {
+            SofteningKernel::None => self.walk(position, theta, |sq_distance| SofteningKernel::None.force_factor(sq_distance, softening_factor)),
+            SofteningKernel::Plummer => self.walk(position, theta, |sq_distance| SofteningKernel::Plummer.force_factor(sq_distance, softening_factor)),
+            SofteningKernel::CubicSpline => self.walk(position, theta, |sq_distance| SofteningKernel::CubicSpline.force_factor(sq_distance, softening_factor)),
+        };
+        [gravity * acceleration[0], gravity * acceleration[1]]
+    }
+
+    /// sum of mass * factor(squared distance) * (dx, dy) over accepted cells and leaf planets
+    #[inline(always)]
+    fn walk<F: Fn(Scalar) -> Scalar>(&self, position: Vec2d<Scalar>, theta: Scalar, factor: F) -> Vec2d<Scalar> {
+        let mut acceleration = [0., 0.];
+        if self.cells.is_empty() {
+            return acceleration;
+        }
+        let sq_theta = theta * theta;
+        let mut stack = [0; STACK_SIZE];
+        let mut pending = 1;
+        while pending > 0 {
+            pending -= 1;
+            let cell = &self.cells[stack[pending]];
+            let (dx, dy) = (cell.center_of_mass[0] - position[0], cell.center_of_mass[1] - position[1]);
+            let sq_distance = dx * dx + dy * dy;
+            let size = 2. * cell.half_size;
+            let inside = (position[0] - cell.center[0]).abs() <= cell.half_size && (position[1] - cell.center[1]).abs() <= cell.half_size;
+            if cell.children.0 == cell.children.1 {
+                let (start, end) = cell.planets;
+                let planets = self.planets.x[start..end].iter().zip(self.planets.y[start..end].iter()).zip(self.planets.mass[start..end].iter());
+                for ((x, y), mass) in planets {
+                    let (dx, dy) = (x - position[0], y - position[1]);
+                    let pair_factor = mass * factor(dx * dx + dy * dy);
+                    acceleration[0] += dx * pair_factor;
+                    acceleration[1] += dy * pair_factor;
+                }
+            } else if !inside && size * size < sq_theta * sq_distance {
+                let pair_factor = cell.mass * factor(sq_distance);
+                acceleration[0] += dx * pair_factor;
+                acceleration[1] += dy * pair_factor;
+            } else {
+                for child in self.children[cell.children.0..cell.children.1].iter() {
+                    stack[pending] = *child;
+                    pending += 1;
+                }
+            }
+        }
+        acceleration
+    }
+}
+
+impl Default for QuadTree {
+    fn default() -> QuadTree {
+        QuadTree::new()
+    }
+}
+
+/// moves the elements matching predicate first, returns their count
+fn partition<P: Fn(usize) -> bool>(order: &mut [usize], predicate: P) -> usize {
+    let mut matching = 0;
+    for i in 0..order.len() {
+        if predicate(order[i]) {
+            order.swap(matching, i);
+            matching += 1;
+        }
+    }
+    matching
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galaxy::Galaxy;
+
+    #[test]
+    fn test_barnes_hut_approximates_direct_sum() {
+        let planets = Galaxy::random_planets(4, 2000, 3000., 10.);
+        let mut arrays = PlanetArrays::new();
+        arrays.update(&planets);
+        let mut tree = QuadTree::new();
+        tree.update(&arrays);
+        let kernel = SofteningKernel::Plummer;
+        let (mut sq_error, mut sq_force) = (0., 0.);
+        for planet in planets.iter() {
+            let expected = arrays.acceleration(planet.position, 0.05, kernel, 0.01);
+            let sq_norm = expected[0] * expected[0] + expected[1] * expected[1];
+            let sq_error_to = |a: Vec2d<Scalar>| (a[0] - expected[0]).powi(2) + (a[1] - expected[1]).powi(2);
+            // theta = 0: every planet, same terms in another order
+            assert!(sq_error_to(tree.acceleration(planet.position, 0., 0.05, kernel, 0.01)) <= 1e-24 * sq_norm);
+            sq_error += sq_error_to(tree.acceleration(planet.position, 0.5, 0.05, kernel, 0.01));
+            sq_force += sq_norm;
+        }
+        // rms force error
+        assert!((sq_error / sq_force).sqrt() < 1e-2, "{}", (sq_error / sq_force).sqrt());
+    }
+
+    #[test]
+    fn test_coincident_planets_are_leaves() {
+        let mut arrays = PlanetArrays::new();
+        arrays.x = vec![1.; 20];
+        arrays.y = vec![2.; 20];
+        arrays.mass = vec![1.; 20];
+        arrays.x.push(50.);
+        arrays.y.push(2.);
+        arrays.mass.push(1.);
+        let mut tree = QuadTree::new();
+        tree.update(&arrays);
+        let acceleration = tree.acceleration([1., 2.], 0.5, 1., SofteningKernel::Plummer, 0.01);
+        assert!(acceleration[0] > 0. && acceleration[0].is_finite());
+        assert_eq!(acceleration[1], 0.);
+    }
+}
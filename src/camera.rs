@@ -42,6 +42,11 @@ impl Camera {
         transform.trans(-self.position[0], -self.position[1]).zoom(self.zoom)
     }
 
+    /// convert world position to view (screen) position
+    pub fn world_to_view_position(&self, position: Vec2d<Scalar>) -> Vec2d<Scalar> {
+        transform_pos(self.world_to_view_transform(identity()), position)
+    }
+
     /// convert view (screen) position to world position
     pub fn view_to_world_position(&self, position: Vec2d<Scalar>) -> Vec2d<Scalar> {
         let transform = identity();
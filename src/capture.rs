@@ -0,0 +1,171 @@
+//! Capture: screenshots and frame sequences of the rendered window (PNG), vector exports (SVG)
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use graphics::math::Scalar;
+use image::RgbaImage;
+use piston::input::{Button, GenericEvent, Key};
+
+use crate::config::Config;
+use crate::galaxy_controller::GalaxyController;
+use crate::svg_export::SvgExporter;
+
+/// Captures rendered frames from the OpenGL back buffer
+pub struct Capture {
+    /// screenshots and recordings are written there
+    pub directory: PathBuf,
+    /// every `record_interval`-th frame is written while recording
+    pub record_interval: u32,
+    /// next screenshot path, if any
+    screenshot: Option<PathBuf>,
+    /// current recording directory, if recording
+    recording: Option<PathBuf>,
+    /// frames written since recording started
+    recorded_frames: u64,
+    /// frames rendered since the last recorded frame
+    frames_since_record: u32,
+    /// SVG exporter
+    pub svg: SvgExporter,
+    /// next SVG export path, if any
+    svg_export: Option<PathBuf>,
+}
+
+impl Capture {
+    /// Creates a new capture from config.
+    pub fn from_config(config: &Config) -> Capture {
+        Capture {
+            directory: PathBuf::from(&config.capture_directory),
+            record_interval: config.record_interval,
+            screenshot: None,
+            recording: None,
+            recorded_frames: 0,
+            frames_since_record: 0,
+            svg: SvgExporter::from_config(config),
+            svg_export: None,
+        }
+    }
+
+    /// Captures the next rendered frame to `path`.
+    pub fn request_screenshot_to(&mut self, path: PathBuf) {
+        self.screenshot = Some(path);
+    }
+
+    /// Captures the next rendered frame to a timestamped file in the capture directory.
+    pub fn request_screenshot(&mut self) {
+        let path = self.directory.join(format!("screenshot-{}.png", timestamp()));
+        self.request_screenshot_to(path);
+    }
+
+    /// true if a screenshot or an SVG export is pending
+    pub fn screenshot_pending(&self) -> bool {
+        self.screenshot.is_some() || self.svg_export.is_some()
+    }
+
+    /// Exports the next rendered scene to `path` (SVG).
+    pub fn request_svg_export_to(&mut self, path: PathBuf) {
+        self.svg_export = Some(path);
+    }
+
+    /// Exports the next rendered scene to a timestamped SVG file in the capture directory.
+    pub fn request_svg_export(&mut self) {
+        let path = self.directory.join(format!("scene-{}.svg", timestamp()));
+        self.request_svg_export_to(path);
+    }
+
+    /// Starts recording (numbered frames in a new directory) or stops recording.
+    pub fn toggle_recording(&mut self) {
+        match self.recording {
+            Some(_) => {
+                self.recording = None;
+                println!("recording stopped after {} frames", self.recorded_frames);
+            }
+            None => {
+                let directory = self.directory.join(format!("recording-{}", timestamp()));
+                println!("recording every {} frame(s) to {}", self.record_interval.max(1), directory.to_string_lossy());
+                self.recording = Some(directory);
+                self.recorded_frames = 0;
+                self.frames_since_record = 0;
+            }
+        }
+    }
+
+    /// Handles events.
+    pub fn event<E: GenericEvent>(&mut self, e: &E) {
+        match e.press_args() {
+            Some(Button::Keyboard(Key::F12)) => self.request_screenshot(),
+            Some(Button::Keyboard(Key::R)) => self.toggle_recording(),
+            Some(Button::Keyboard(Key::E)) => self.request_svg_export(),
+            _ => ()
+        }
+    }
+
+    /// Writes pending screenshot, recording frames and SVG export.
+    /// Must be called right after rendering, before buffers are swapped.
+    pub fn after_render(&mut self, controller: &GalaxyController, view_size: [Scalar; 2], draw_size: [u32; 2]) {
+        if let Some(path) = self.svg_export.take() {
+            match self.svg.export(controller, view_size, &path) {
+                Ok(()) => println!("saved {}", path.to_string_lossy()),
+                Err(e) => println!("could not save {}: {}", path.to_string_lossy(), e),
+            }
+        }
+        let record_frame = match &self.recording {
+            Some(directory) if self.frames_since_record == 0 => {
+                Some(directory.join(format!("frame-{:06}.png", self.recorded_frames)))
+            }
+            _ => None,
+        };
+        if self.recording.is_some() {
+            self.frames_since_record = (self.frames_since_record + 1) % self.record_interval.max(1);
+        }
+        if record_frame.is_some() {
+            self.recorded_frames += 1;
+        }
+        if self.screenshot.is_none() && record_frame.is_none() {
+            return;
+        }
+        let image = read_back_buffer(draw_size);
+        if let Some(path) = self.screenshot.take() {
+            save(&image, &path);
+        }
+        if let Some(path) = record_frame {
+            save(&image, &path);
+        }
+    }
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+fn save(image: &RgbaImage, path: &Path) {
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            println!("could not create {}: {}", parent.to_string_lossy(), e);
+            return;
+        }
+    }
+    match image.save(path) {
+        Ok(()) => println!("saved {}", path.to_string_lossy()),
+        Err(e) => println!("could not save {}: {}", path.to_string_lossy(), e),
+    }
+}
+
+/// Reads the current (back) color buffer.
+fn read_back_buffer(draw_size: [u32; 2]) -> RgbaImage {
+    let (width, height) = (draw_size[0], draw_size[1]);
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut _);
+    }
+    // OpenGL rows go bottom to top
+    let row = (width * 4) as usize;
+    let mut flipped: Vec<u8> = pixels.chunks(row).rev().flat_map(|r| r.iter().cloned()).collect();
+    // the window itself is opaque
+    for alpha in flipped.iter_mut().skip(3).step_by(4) {
+        *alpha = 255;
+    }
+    RgbaImage::from_raw(width, height, flipped).expect("buffer should match image size")
+}
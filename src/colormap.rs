@@ -0,0 +1,232 @@
+//! Planet coloring: maps a physical quantity to a colormap
+
+use graphics::math::{Scalar, square_len};
+use graphics::types::Color;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::galaxy_controller::GalaxyController;
+
+/// viridis control points (evenly spaced)
+const VIRIDIS: [[f32; 3]; 5] = [
+    [0.267, 0.005, 0.329],
+    [0.231, 0.322, 0.545],
+    [0.129, 0.569, 0.549],
+    [0.369, 0.788, 0.384],
+    [0.992, 0.906, 0.145],
+];
+
+/// plasma control points (evenly spaced)
+const PLASMA: [[f32; 3]; 5] = [
+    [0.051, 0.031, 0.529],
+    [0.494, 0.012, 0.659],
+    [0.800, 0.278, 0.471],
+    [0.973, 0.584, 0.251],
+    [0.941, 0.976, 0.129],
+];
+
+/// cool to warm diverging control points (evenly spaced)
+const DIVERGING: [[f32; 3]; 5] = [
+    [0.230, 0.299, 0.754],
+    [0.552, 0.690, 0.996],
+    [0.866, 0.866, 0.866],
+    [0.956, 0.604, 0.486],
+    [0.706, 0.016, 0.150],
+];
+
+/// physical quantity planets are colored by
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    /// single planet color (or texture)
+    Uniform,
+    /// |velocity|
+    Speed,
+    /// mass
+    Mass,
+    /// 1/2 m v^2
+    KineticEnergy,
+    /// m |a| (net gravitational force, last update)
+    NetForce,
+    /// number of collision impulses received
+    Collisions,
+}
+
+impl ColorMode {
+    /// color mode name
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Uniform => "uniform",
+            ColorMode::Speed => "speed",
+            ColorMode::Mass => "mass",
+            ColorMode::KineticEnergy => "kinetic_energy",
+            ColorMode::NetForce => "net_force",
+            ColorMode::Collisions => "collisions",
+        }
+    }
+
+    /// next color mode (cycling)
+    pub fn next(self) -> ColorMode {
+        match self {
+            ColorMode::Uniform => ColorMode::Speed,
+            ColorMode::Speed => ColorMode::Mass,
+            ColorMode::Mass => ColorMode::KineticEnergy,
+            ColorMode::KineticEnergy => ColorMode::NetForce,
+            ColorMode::NetForce => ColorMode::Collisions,
+            ColorMode::Collisions => ColorMode::Uniform,
+        }
+    }
+}
+
+/// Colormap: maps [0, 1] to a color
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Colormap {
+    /// perceptually uniform, dark blue to yellow
+    Viridis,
+    /// perceptually uniform, dark blue to yellow through magenta
+    Plasma,
+    /// blue to red through light grey
+    Diverging,
+}
+
+impl Colormap {
+    /// next colormap (cycling)
+    pub fn next(self) -> Colormap {
+        match self {
+            Colormap::Viridis => Colormap::Plasma,
+            Colormap::Plasma => Colormap::Diverging,
+            Colormap::Diverging => Colormap::Viridis,
+        }
+    }
+
+    /// color at t, t is clamped to [0, 1]
+    pub fn color(self, t: Scalar) -> Color {
+        let points = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Plasma => &PLASMA,
+            Colormap::Diverging => &DIVERGING,
+        };
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+        let x = t * (points.len() - 1) as Scalar;
+        let i = (x.floor() as usize).min(points.len() - 2);
+        let f = (x - i as Scalar) as f32;
+        let (a, b) = (points[i], points[i + 1]);
+        [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f, a[2] + (b[2] - a[2]) * f, 1.0]
+    }
+}
+
+/// How values are mapped to [0, 1]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorScale {
+    /// linear between min and max
+    Linear,
+    /// linear between log10(min) and log10(max), values <= 0 are clamped
+    Log,
+}
+
+impl ColorScale {
+    /// value => scaled value
+    pub fn apply(self, value: Scalar) -> Scalar {
+        match self {
+            ColorScale::Linear => value,
+            ColorScale::Log => value.max(Scalar::MIN_POSITIVE).log10(),
+        }
+    }
+}
+
+/// Colors planets by a physical quantity
+pub struct PlanetColoring {
+    /// quantity planets are colored by
+    pub mode: ColorMode,
+    /// colormap
+    pub colormap: Colormap,
+    /// value scaling
+    pub scale: ColorScale,
+}
+
+impl PlanetColoring {
+    /// Creates a new planet coloring from config.
+    pub fn from_config(config: &Config) -> PlanetColoring {
+        PlanetColoring {
+            mode: config.color_mode,
+            colormap: config.colormap,
+            scale: config.color_scale,
+        }
+    }
+
+    /// quantity for each planet, None in uniform mode
+    pub fn values(&self, controller: &GalaxyController) -> Option<Vec<Scalar>> {
+        let planets = &controller.galaxy.planets;
+        let values = match self.mode {
+            ColorMode::Uniform => return None,
+            ColorMode::Speed => planets.iter().map(|p| square_len(p.velocity).sqrt()).collect(),
+            ColorMode::Mass => planets.iter().map(|p| p.mass).collect(),
+            ColorMode::KineticEnergy => planets.iter().map(|p| 0.5 * p.mass * square_len(p.velocity)).collect(),
+            ColorMode::NetForce => planets.iter().enumerate().map(|(i, p)| {
+                // accelerations are missing for planets added since the last update
+                controller.accelerations.get(i).map_or(0., |a| p.mass * square_len(*a).sqrt())
+            }).collect(),
+            ColorMode::Collisions => planets.iter().map(|p| Scalar::from(p.collisions)).collect(),
+        };
+        Some(values)
+    }
+
+    /// (min, max) values, None in uniform mode or without planets
+    pub fn range(&self, controller: &GalaxyController) -> Option<(Scalar, Scalar)> {
+        let values = self.values(controller)?;
+        PlanetColoring::value_range(&values)
+    }
+
+    /// color for each planet, None in uniform mode
+    pub fn colors(&self, controller: &GalaxyController) -> Option<Vec<Color>> {
+        let values = self.values(controller)?;
+        let range = PlanetColoring::value_range(&values).unwrap_or((0., 0.));
+        Some(values.iter().map(|v| self.color(*v, range)).collect())
+    }
+
+    /// color of value w.r to (min, max) range
+    pub fn color(&self, value: Scalar, range: (Scalar, Scalar)) -> Color {
+        let (min, max) = (self.scale.apply(range.0), self.scale.apply(range.1));
+        let t = if max > min { (self.scale.apply(value) - min) / (max - min) } else { 0.5 };
+        self.colormap.color(t)
+    }
+
+    fn value_range(values: &[Scalar]) -> Option<(Scalar, Scalar)> {
+        values.iter().filter(|v| v.is_finite()).fold(None, |range, v| match range {
+            None => Some((*v, *v)),
+            Some((min, max)) => Some((min.min(*v), max.max(*v))),
+        })
+    }
+}
+
+/// 8-bit channels of color (clamped to [0, 1])
+pub fn to_rgba8(color: Color) -> [u8; 4] {
+    let channel = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+    [channel(color[0]), channel(color[1]), channel(color[2]), channel(color[3])]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colormap_bounds() {
+        assert_eq!(Colormap::Viridis.color(0.), [0.267, 0.005, 0.329, 1.0]);
+        assert_eq!(Colormap::Viridis.color(1.), [0.992, 0.906, 0.145, 1.0]);
+        assert_eq!(Colormap::Plasma.color(-1.), Colormap::Plasma.color(0.));
+        assert_eq!(Colormap::Plasma.color(2.), Colormap::Plasma.color(1.));
+        assert_eq!(Colormap::Diverging.color(0.5), [0.866, 0.866, 0.866, 1.0]);
+    }
+
+    #[test]
+    fn test_log_scale() {
+        let coloring = PlanetColoring { mode: ColorMode::Mass, colormap: Colormap::Diverging, scale: ColorScale::Log };
+        // 10 is halfway between 1 and 100 in log scale
+        assert_eq!(coloring.color(10., (1., 100.)), Colormap::Diverging.color(0.5));
+        // values <= 0 are clamped to the colormap minimum
+        assert_eq!(coloring.color(0., (1., 100.)), Colormap::Diverging.color(0.));
+    }
+}
@@ -2,19 +2,65 @@
 
 extern crate serde_json;
 
-use std::fs::File;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use graphics::math::{Scalar, Vec2d};
-use graphics::types::Color;
+use graphics::math::{Scalar, square_len, sub, Vec2d};
+use graphics::types::{Color, FontSize};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-use crate::galaxy::Planet;
+use crate::colormap::{Colormap, ColorMode, ColorScale};
+use crate::galaxy::{Galaxy, Planet};
+use crate::gravity_kernel::GravitySolver;
+use crate::softening::SofteningKernel;
 
+/// Config file format, picked from the file extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    /// .json (and unknown extensions)
+    Json,
+    /// .toml
+    Toml,
+    /// .yaml or .yml
+    Yaml,
+    /// .ron
+    Ron,
+}
+
+impl ConfigFormat {
+    /// Format of a config file, JSON unless the extension says otherwise.
+    pub fn from_path(path: &Path) -> ConfigFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("ron") => ConfigFormat::Ron,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+/// All formats share this model: missing fields get `Config::default()` values
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(default = "Config::default")]
 pub struct Config {
+    /// config format version, files without version are version 1
+    /// older versions are upgraded when loaded (see `migrate_file`)
+    #[serde(default = "legacy_version")]
+    pub version: u32,
     /// G, the gravitational constant: the higher the stronger gravity will be
     pub gravity: Scalar,
-    /// a small positive value to avoid dividing by 0 e.g. 0.01
+    /// squared softening length > 0 e.g. 0.01: gravity is softened for planets closer than sqrt(softening_factor)
+    /// (unless softening_kernel is "none"), also sets the adaptive time step of close encounters
     pub softening_factor: Scalar,
+    /// force law between close planets (see `SofteningKernel`)
+    /// "none" (Newtonian), "plummer" or "cubic_spline" (GADGET)
+    pub softening_kernel: SofteningKernel,
+    /// how accelerations are computed: "direct" (every pair, exact) or "barnes_hut" (quadtree, much faster for thousands of planets)
+    pub gravity_solver: GravitySolver,
+    /// Barnes–Hut opening angle >= 0.0: planet groups seen under a smaller angle (size / distance) pull as a single mass
+    /// 0.0: exact, the higher the faster and less accurate e.g. 0.5
+    pub barnes_hut_theta: Scalar,
     /// restitution factor >= 0.0: "bounciness" of objects
     /// 0.0: no bounce
     pub restitution_factor: Scalar,
@@ -22,30 +68,137 @@ pub struct Config {
     /// 2.0: zoom => 2 times bigger, de-zoom => 2 times smaller
     pub zoom_factor: Scalar,
     /// default zoom
+    #[serde(alias = "default_zom")]
     pub default_zoom: Scalar,
     /// camera_speed > 0.0
     /// distance/frame
     pub camera_speed: Scalar,
     /// camera starting position
+    #[serde(with = "xy")]
+    #[schemars(with = "Xy")]
     pub camera_position: Vec2d<Scalar>,
     /// window size (pixels)
+    #[serde(with = "size")]
+    #[schemars(with = "Size")]
     pub window_size: [u32; 2],
-    /// frame time step > 0.0
-    /// the higher the faster the simulation will be.
-    /// NB: collisions may fail if value is too high relatively to objects velocity
+    /// frame time step > 0.0: simulated time per physics step
+    /// the higher the fewer steps per second (see simulation_speed).
+    /// NB: collisions are detected continuously (at impact time), but orbits get less accurate
+    /// if value is too high (unless adaptive_time_step subdivides it)
     pub frame_time_step: Scalar,
+    /// simulated time per wall-clock second (the simulation advances in frame_time_step steps)
+    pub simulation_speed: Scalar,
+    /// physics steps per rendered frame at most, the simulation slows down when it cannot keep up
+    pub max_steps_per_frame: u32,
+    /// subdivide frame_time_step during close encounters and for fast planets
+    /// step = time_step_accuracy * min over planets of sqrt(sqrt(softening_factor) / |acceleration|) and radius / |velocity|
+    pub adaptive_time_step: bool,
+    /// adaptive step accuracy > 0.0, the lower the more accurate (and slower)
+    pub time_step_accuracy: Scalar,
+    /// shortest adaptive step > 0.0, bounds the number of steps per frame (frame_time_step / min_time_step)
+    pub min_time_step: Scalar,
     /// background color (float RGBA)
     /// e.g. [1., 0., 0., 0.8] => RED with 0.8 opacity
+    #[serde(with = "rgba")]
+    #[schemars(with = "Rgba")]
     pub background_color: Color,
     /// planet color
     /// e.g. [1., 0., 0., 0.8] => RED with 0.8 opacity
+    #[serde(with = "rgba")]
+    #[schemars(with = "Rgba")]
     pub planet_color: Color,
-    /// planet texture path
+    /// planet texture path, relative to the config file setting it
     pub planet_texture_path: Option<String>,
     /// default planet radius > 0.
     pub planet_radius: Scalar,
     /// planets
+    #[serde(with = "planets")]
+    #[schemars(with = "Vec<PlanetConfig>")]
     pub planets: Vec<Planet>,
+    /// show the HUD (fps, planets, simulated time...) at startup
+    pub hud_enabled: bool,
+    /// HUD font path (TrueType), relative to the config file setting it
+    pub hud_font_path: Option<String>,
+    /// HUD font size (pixels)
+    pub hud_font_size: FontSize,
+    /// HUD text color
+    /// e.g. [1., 0., 0., 0.8] => RED with 0.8 opacity
+    #[serde(with = "rgba")]
+    #[schemars(with = "Rgba")]
+    pub hud_color: Color,
+    /// show velocity arrows at startup
+    pub show_velocities: bool,
+    /// velocity arrow length = velocity * velocity_scale (world units)
+    pub velocity_scale: Scalar,
+    /// show net gravitational acceleration arrows at startup
+    pub show_accelerations: bool,
+    /// acceleration arrow length = acceleration * acceleration_scale (world units)
+    pub acceleration_scale: Scalar,
+    /// show collision normals and impulses at startup
+    pub show_contacts: bool,
+    /// impulse arrow length = impulse * impulse_scale (world units)
+    pub impulse_scale: Scalar,
+    /// quantity planets are colored by
+    /// "uniform" (planet color or texture), "speed", "mass", "kinetic_energy", "net_force" or "collisions"
+    pub color_mode: ColorMode,
+    /// colormap used when color mode is not uniform
+    /// "viridis", "plasma" or "diverging"
+    pub colormap: Colormap,
+    /// colormap scaling: "linear" or "log"
+    pub color_scale: ColorScale,
+    /// show the gravitational potential heatmap at startup
+    pub show_potential: bool,
+    /// show the gravitational potential iso-contours at startup
+    pub show_potential_contours: bool,
+    /// potential sampling grid cell size > 0 (pixels)
+    pub potential_cell_size: Scalar,
+    /// potential is sampled again every potential_refresh_interval frames
+    pub potential_refresh_interval: u32,
+    /// number of potential iso-contours
+    pub potential_contour_levels: usize,
+    /// show the world grid, origin axes and scale bar at startup
+    pub show_grid: bool,
+    /// world grid color
+    /// e.g. [1., 0., 0., 0.8] => RED with 0.8 opacity
+    #[serde(with = "rgba")]
+    #[schemars(with = "Rgba")]
+    pub grid_color: Color,
+    /// minimum world grid spacing > 0 (pixels)
+    /// the spacing snaps to 1/2/5 x 10^n world units
+    pub grid_min_spacing: Scalar,
+    /// screenshots and recordings directory
+    pub capture_directory: String,
+    /// every record_interval-th frame is written while recording
+    pub record_interval: u32,
+    /// number of past positions kept per planet (trails), 0 disables trails
+    pub trail_length: usize,
+    /// SVG export: draw planet trails as polylines
+    pub svg_trails: bool,
+    /// SVG export: draw velocity arrows (velocity_scale applies)
+    pub svg_velocities: bool,
+    /// random scene seed: planets are replaced by random_planet_count random planets
+    pub seed: Option<u64>,
+    /// number of random planets (when seed is set)
+    pub random_planet_count: usize,
+    /// random planets are placed in a disc of random_scene_radius around the origin
+    pub random_scene_radius: Scalar,
+    /// config files included before this one, paths are relative to this file
+    /// later files override earlier ones (this file last), planets are added up
+    pub include: Vec<Include>,
+}
+
+/// An included config file
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Include {
+    /// config file path, relative to the including file
+    pub path: String,
+    /// included planets are moved by offset (after rotation)
+    #[serde(default, with = "xy")]
+    #[schemars(with = "Xy")]
+    pub offset: Vec2d<Scalar>,
+    /// included planets are rotated by rotation (degrees, clockwise on screen) around the origin
+    #[serde(default)]
+    pub rotation: Scalar,
 }
 
 impl Config {
@@ -55,8 +208,12 @@ impl Config {
         planets.push(Planet::default(0., 40., 10., 1));
         planets.push(Planet::default(0., 80., 20., 2));
         Config {
+            version: CONFIG_VERSION,
             gravity: 0.05,
             softening_factor: 0.01,
+            softening_kernel: SofteningKernel::Plummer,
+            gravity_solver: GravitySolver::Direct,
+            barnes_hut_theta: 0.5,
             restitution_factor: 0.2,
             zoom_factor: 2.0,
             default_zoom: 1.0,
@@ -64,100 +221,657 @@ impl Config {
             camera_position: [0., 0.],
             window_size: [512; 2],
             frame_time_step: 0.1,
+            simulation_speed: 12.,
+            max_steps_per_frame: 10,
+            adaptive_time_step: true,
+            time_step_accuracy: 0.1,
+            min_time_step: 0.001,
             background_color: [0.2, 0.2, 0.2, 1.0],
             planet_color: [1.0, 0.6, 0.0, 1.0],
             planet_texture_path: None,
             planet_radius: 10.,
-            planets
+            planets,
+            hud_enabled: true,
+            hud_font_path: Some(String::from("res/DejaVuSansMono.ttf")),
+            hud_font_size: 12,
+            hud_color: [1.0, 1.0, 1.0, 1.0],
+            show_velocities: false,
+            velocity_scale: 10.,
+            show_accelerations: false,
+            acceleration_scale: 100.,
+            show_contacts: false,
+            impulse_scale: 10.,
+            color_mode: ColorMode::Uniform,
+            colormap: Colormap::Viridis,
+            color_scale: ColorScale::Linear,
+            show_potential: false,
+            show_potential_contours: false,
+            potential_cell_size: 8.,
+            potential_refresh_interval: 10,
+            potential_contour_levels: 12,
+            show_grid: false,
+            grid_color: [1.0, 1.0, 1.0, 0.2],
+            grid_min_spacing: 50.,
+            capture_directory: String::from("captures"),
+            record_interval: 1,
+            trail_length: 200,
+            svg_trails: true,
+            svg_velocities: false,
+            seed: None,
+            random_planet_count: 20,
+            random_scene_radius: 300.,
+            include: vec![],
         }
     }
 
-    fn get_scalar_or(json: &serde_json::Value, default_value: Scalar) -> Scalar {
-        match json {
-            serde_json::Value::Number(x) => x.as_f64().unwrap(),
-            _ => default_value
+    /// Parses a config in the given format.
+    pub fn parse(text: &str, format: ConfigFormat) -> Result<Config, String> {
+        match format {
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            ConfigFormat::Ron => ron::de::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Creates a config from a JSON value.
+    pub fn from_value(json: &serde_json::Value) -> Result<Config, String> {
+        Config::deserialize(json).map_err(|e| e.to_string())
+    }
+
+    /// JSON value of this config, `from_value` gives it back.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("config should be serializable")
+    }
+
+    /// JSON schema of config files, generated from this model (see res/config.schema).
+    pub fn schema() -> String {
+        let schema = schemars::schema_for!(Config);
+        serde_json::to_string_pretty(&schema).expect("schema should be serializable")
+    }
+
+    /// Applies a `key=value` override to a JSON config e.g. `gravity=0.1` or `camera_position.x=-50`
+    /// value is parsed as JSON, as a string otherwise e.g. `colormap=plasma`.
+    pub fn set(json: &mut serde_json::Value, assignment: &str) -> Result<(), String> {
+        let mut split = assignment.splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) if !key.is_empty() => (key, value),
+            _ => return Err(format!("{}: expected key=value", assignment)),
+        };
+        let value = serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+        // known fields are the ones of the default config
+        let mut known = &Config::default().to_json();
+        let mut target = json;
+        for name in key.split('.') {
+            known = match known.get(name) {
+                Some(field) => field,
+                None => return Err(format!("{}: unknown config field", key)),
+            };
+            if !target.is_object() {
+                *target = serde_json::json!({});
+            }
+            target = &mut target[name];
         }
+        *target = value;
+        Ok(())
     }
 
-    fn get_vector2d_or(json: &serde_json::Value, default_value: Vec2d<Scalar>) -> Vec2d<Scalar> {
-        let x_value = &json["x"];
-        let y_value = &json["y"];
-        match (x_value, y_value) {
-            (serde_json::Value::Number(x), serde_json::Value::Number(y)) => [x.as_f64().unwrap(), y.as_f64().unwrap()],
-            _ => default_value
+    /// Applies overrides (see `set`), invalid ones are reported and skipped.
+    fn apply_overrides(config: Config, overrides: &[String]) -> Config {
+        if overrides.is_empty() {
+            return config;
         }
+        let mut json = config.to_json();
+        for assignment in overrides {
+            let mut overridden = json.clone();
+            match Config::set(&mut overridden, assignment).and_then(|_| Config::from_value(&overridden)) {
+                Ok(_) => json = overridden,
+                Err(e) => println!("ignoring override {}: {}", assignment, e),
+            }
+        }
+        Config::from_value(&json).unwrap_or(config)
     }
 
-    fn get_rgba_or(json: &serde_json::Value, default_value: Color) -> Color {
-        let r_value = &json["r"];
-        let g_value = &json["g"];
-        let b_value = &json["b"];
-        let a_value = &json["a"];
-        match (r_value, g_value, b_value, a_value) {
-            (serde_json::Value::Number(r), serde_json::Value::Number(g), serde_json::Value::Number(b), serde_json::Value::Number(a)) =>
-                [r.as_f64().unwrap() as f32, g.as_f64().unwrap() as f32, b.as_f64().unwrap() as f32, a.as_f64().unwrap() as f32],
-            _ => default_value
+    /// Loads config from path (format from the extension) and its includes then applies overrides (see `set`).
+    pub fn try_load(path: &Path, overrides: &[String]) -> Result<Config, String> {
+        Config::try_load_files(path, overrides, &mut vec![])
+    }
+
+    /// Same as `try_load`, the files read (path and its includes) are pushed to files, even when loading fails.
+    pub fn try_load_files(path: &Path, overrides: &[String], files: &mut Vec<PathBuf>) -> Result<Config, String> {
+        let mut json = Config::default().to_json();
+        for (key, value) in Config::load_layer(path, &mut vec![], files)? {
+            json[key] = value;
         }
+        let config = Config::from_value(&json)?;
+        Ok(Config::apply_overrides(config, overrides))
     }
 
-    fn get_planets_or(json: &serde_json::Value, default_value: Vec<Planet>) -> Vec<Planet> {
-        match json {
-            serde_json::Value::Array(planets_value_vec) => {
-                let mut i: usize = 0;
-                let mut planets: Vec<Planet> = vec![];
-                for planet_value in planets_value_vec {
-                    let x_value = &planet_value["x"];
-                    let y_value = &planet_value["y"];
-                    let r_value = &planet_value["r"];
-                    match (x_value, y_value, r_value) {
-                        (serde_json::Value::Number(x), serde_json::Value::Number(y), serde_json::Value::Number(r)) => {
-                            planets.push(Planet::default(x.as_f64().unwrap(), y.as_f64().unwrap(), r.as_f64().unwrap(), i));
-                            i += 1;
-                        }
-                        _ => { println!("error while parsing planets") }
+    /// Fields set by the file at path or by its includes (JSON), planets are added up.
+    /// File paths (see `PATH_KEYS`) are resolved against the directory of the file setting them.
+    /// path and the includes read are pushed to files.
+    fn load_layer(path: &Path, loading: &mut Vec<PathBuf>, files: &mut Vec<PathBuf>) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+        let error = |e: String| format!("{}: {}", path.to_string_lossy(), e);
+        if !files.iter().any(|file| file == path) {
+            files.push(path.to_path_buf());
+        }
+        let canonical = fs::canonicalize(path).map_err(|e| error(e.to_string()))?;
+        if loading.contains(&canonical) {
+            return Err(error(String::from("include cycle")));
+        }
+        let text = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let format = ConfigFormat::from_path(path);
+        let config = Config::parse(&text, format).map_err(error)?;
+        let keys = top_level_keys(&text, format).map_err(error)?;
+        if config.version > CONFIG_VERSION {
+            return Err(error(format!("version {} is newer than supported version {}", config.version, CONFIG_VERSION)));
+        }
+        if config.version < CONFIG_VERSION {
+            println!(
+                "{}: version {} config upgraded to version {}, run `galaxy migrate-config {}` to update the file",
+                path.to_string_lossy(), config.version, CONFIG_VERSION, path.to_string_lossy()
+            );
+        }
+
+        loading.push(canonical);
+        let mut layer = serde_json::Map::new();
+        let mut planets: Vec<serde_json::Value> = vec![];
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in config.include.iter() {
+            for (key, value) in Config::load_layer(&directory.join(&include.path), loading, files)? {
+                match (key.as_str(), value) {
+                    ("planets", serde_json::Value::Array(included_planets)) => {
+                        planets.extend(included_planets.into_iter().map(|planet| include.transform(planet)))
+                    }
+                    (_, value) => {
+                        layer.insert(key, value);
                     }
                 }
-                planets
             }
-            _ => default_value
         }
+        loading.pop();
+
+        let json = config.to_json();
+        for key in keys {
+            let key = ALIASES.iter().find(|(alias, _)| *alias == key).map_or(key.as_str(), |(_, name)| name);
+            // unknown keys are ignored, like when loading a single file
+            match (key, json.get(key)) {
+                // loaded configs are upgraded to the latest version
+                ("include", _) | ("version", _) | (_, None) => (),
+                ("planets", Some(serde_json::Value::Array(own_planets))) => planets.extend(own_planets.iter().cloned()),
+                (_, Some(serde_json::Value::String(file))) if PATH_KEYS.contains(&key) => {
+                    let file = directory.join(file).to_string_lossy().into_owned();
+                    layer.insert(key.to_string(), serde_json::Value::String(file));
+                }
+                (_, Some(value)) => {
+                    layer.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+        if !planets.is_empty() || layer.contains_key("planets") {
+            layer.insert(String::from("planets"), serde_json::Value::Array(planets));
+        }
+        Ok(layer)
     }
 
-    fn get_path_or(json: &serde_json::Value, default_value: Option<String>) -> Option<String> {
-        match json {
-            serde_json::Value::String(path) => Some(path.clone()),
-            _ => default_value.clone()
+    /// Loads config from path then applies overrides (see `set`), falls back to default config.
+    pub fn load(path: &Path, overrides: &[String]) -> Config {
+        match Config::try_load(path, overrides) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("could not load {}: {}, using default config instead", path.to_string_lossy(), e);
+                Config::apply_overrides(Config::default(), overrides)
+            }
         }
     }
 
-    pub fn from_json(json_file: File) -> Config {
-        let default_config = Config::default();
-        let json: serde_json::Value = serde_json::from_reader(json_file).expect("file should have proper JSON");
-        Config {
-            gravity: Config::get_scalar_or(&json["gravity"], default_config.gravity),
-            softening_factor: Config::get_scalar_or(&json["softening_factor"], default_config.softening_factor),
-            restitution_factor: Config::get_scalar_or(&json["restitution_factor"], default_config.restitution_factor),
-            zoom_factor: Config::get_scalar_or(&json["zoom_factor"], default_config.zoom_factor),
-            default_zoom: Config::get_scalar_or(&json["default_zom"], default_config.default_zoom),
-            camera_speed: Config::get_scalar_or(&json["camera_speed"], default_config.camera_speed),
-            camera_position: Config::get_vector2d_or(&json["camera_position"], default_config.camera_position),
-            window_size: default_config.window_size,
-            frame_time_step: Config::get_scalar_or(&json["frame_time_step"], default_config.frame_time_step),
-            background_color: Config::get_rgba_or(&json["background_color"], default_config.background_color),
-            planet_color: Config::get_rgba_or(&json["planet_color"], default_config.planet_color),
-            planet_texture_path: Config::get_path_or(&json["planet_texture_path"], default_config.planet_texture_path),
-            planet_radius: Config::get_scalar_or(&json["planet_radius"], default_config.planet_radius),
-            planets: Config::get_planets_or(&json["planets"], default_config.planets),
-        }
-    }
-
-    pub fn from_path(path: &Path) -> Config {
-        match File::open(path) {
-            Ok(json_file) => Config::from_json(json_file),
-            Err(_) => {
-                println!("could not open {}, using default config instead", path.to_string_lossy());
-                Config::default()
-            },
-        }
-    }
-}
\ No newline at end of file
+    /// Rewrites the config file at path in the latest version (same format, includes are left as is).
+    /// Only the fields set in the file are written, except in RON where every field is.
+    /// Returns warnings about deprecated and unknown keys.
+    pub fn migrate_file(path: &Path) -> Result<Vec<String>, String> {
+        let error = |e: String| format!("{}: {}", path.to_string_lossy(), e);
+        let text = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let format = ConfigFormat::from_path(path);
+        let config = Config::parse(&text, format).map_err(error)?;
+        let keys = top_level_keys(&text, format).map_err(error)?;
+        if config.version > CONFIG_VERSION {
+            return Err(error(format!("version {} is newer than supported version {}", config.version, CONFIG_VERSION)));
+        }
+
+        let mut warnings: Vec<String> = vec![];
+        let json = config.to_json();
+        let mut migrated = serde_json::Map::new();
+        for key in keys.iter() {
+            let name = match ALIASES.iter().find(|(alias, _)| alias == key) {
+                Some((_, name)) => {
+                    warnings.push(format!("{} is deprecated, renamed to {}", key, name));
+                    *name
+                }
+                None => key.as_str(),
+            };
+            match json.get(name) {
+                Some(value) => {
+                    migrated.insert(name.to_string(), value.clone());
+                }
+                None => warnings.push(format!("{} is not a config field, dropped", key)),
+            }
+        }
+        if config.version < 2 && migrated.contains_key("planets") {
+            warnings.push(String::from("planets {x, y, r} converted to {position, radius, velocity}"));
+        }
+        migrated.insert(String::from("version"), serde_json::json!(CONFIG_VERSION));
+
+        let migrated = serde_json::Value::Object(migrated);
+        let text = match format {
+            ConfigFormat::Json => serde_json::to_string_pretty(&migrated).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::Value::try_from(&migrated).and_then(|v| toml::to_string_pretty(&v)).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(&migrated).map_err(|e| e.to_string()),
+            // RON structs cannot be written from JSON maps: every field is written
+            ConfigFormat::Ron => Config::from_value(&migrated)
+                .and_then(|config| ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())),
+        }.map_err(error)?;
+        fs::write(path, text + "\n").map_err(|e| error(e.to_string()))?;
+        Ok(warnings)
+    }
+
+    /// Issues that only affect the window (e.g. missing HUD font: HUD text is not drawn), not checked by `validate`
+    /// so that headless runs work from any directory.
+    pub fn window_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if let Some(path) = &self.hud_font_path {
+            if !Path::new(path).is_file() {
+                warnings.push(format!("hud_font_path: {} not found, HUD text is not displayed", path));
+            }
+        }
+        warnings
+    }
+
+    /// Checks the invariants stated above, every violation is listed in the report (one per line).
+    pub fn validate(&self) -> Result<(), String> {
+        let mut violations: Vec<String> = vec![];
+        let mut check = |valid: bool, violation: String| {
+            if !valid {
+                violations.push(violation);
+            }
+        };
+        check(self.gravity.is_finite(), format!("gravity must be finite (got {})", self.gravity));
+        check(self.softening_factor > 0., format!("softening_factor must be > 0.0 (got {})", self.softening_factor));
+        check(self.barnes_hut_theta >= 0., format!("barnes_hut_theta must be >= 0.0 (got {})", self.barnes_hut_theta));
+        check(self.restitution_factor >= 0., format!("restitution_factor must be >= 0.0 (got {})", self.restitution_factor));
+        check(self.zoom_factor > 1., format!("zoom_factor must be > 1.0 (got {})", self.zoom_factor));
+        check(self.default_zoom > 0., format!("default_zoom must be > 0.0 (got {})", self.default_zoom));
+        check(self.camera_speed > 0., format!("camera_speed must be > 0.0 (got {})", self.camera_speed));
+        check(self.window_size[0] > 0 && self.window_size[1] > 0, format!("window_size must be > 0 (got {}x{})", self.window_size[0], self.window_size[1]));
+        check(self.frame_time_step > 0., format!("frame_time_step must be > 0.0 (got {})", self.frame_time_step));
+        check(self.simulation_speed > 0., format!("simulation_speed must be > 0.0 (got {})", self.simulation_speed));
+        check(self.max_steps_per_frame > 0, String::from("max_steps_per_frame must be > 0"));
+        check(self.time_step_accuracy > 0., format!("time_step_accuracy must be > 0.0 (got {})", self.time_step_accuracy));
+        check(self.min_time_step > 0., format!("min_time_step must be > 0.0 (got {})", self.min_time_step));
+        check(self.planet_radius > 0., format!("planet_radius must be > 0.0 (got {})", self.planet_radius));
+        check(self.potential_cell_size > 0., format!("potential_cell_size must be > 0.0 (got {})", self.potential_cell_size));
+        check(self.grid_min_spacing > 0., format!("grid_min_spacing must be > 0.0 (got {})", self.grid_min_spacing));
+        if let Some(path) = &self.planet_texture_path {
+            check(Path::new(path).is_file(), format!("planet_texture_path: {} not found", path));
+        }
+        if self.seed.is_some() {
+            check(self.random_scene_radius > 0., format!("random_scene_radius must be > 0.0 (got {})", self.random_scene_radius));
+        }
+        // random planets of radius <= 0 cannot be created (only the planet_radius violation is reported)
+        let planets = if self.seed.is_none() || self.planet_radius > 0. { Galaxy::from_config(self).planets } else { vec![] };
+        for (i, planet) in planets.iter().enumerate() {
+            check(planet.r > 0., format!("planet {}: radius must be > 0.0 (got {})", i, planet.r));
+            for (j, other_planet) in planets.iter().enumerate().skip(i + 1) {
+                let distance = square_len(sub(other_planet.position, planet.position)).sqrt();
+                check(distance >= planet.r + other_planet.r, format!("planets {} and {} overlap", i, j));
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations.iter().map(|v| format!("  - {}", v)).collect::<Vec<String>>().join("\n"))
+        }
+    }
+}
+
+/// latest config format version
+/// 2: planets {position, radius, velocity} instead of {x, y, r}, default_zoom instead of default_zom
+pub const CONFIG_VERSION: u32 = 2;
+
+/// version of files without version
+fn legacy_version() -> u32 {
+    1
+}
+
+/// former field names, deprecated
+const ALIASES: &[(&str, &str)] = &[("default_zom", "default_zoom")];
+
+/// fields holding a file path, relative to the config file setting them
+const PATH_KEYS: &[&str] = &["planet_texture_path", "hud_font_path"];
+
+impl Include {
+    /// included planet (JSON) rotated then moved, its velocity is rotated too
+    fn transform(&self, mut planet: serde_json::Value) -> serde_json::Value {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let rotate = |v: &serde_json::Value| {
+            let (x, y) = (v["x"].as_f64().unwrap_or(0.), v["y"].as_f64().unwrap_or(0.));
+            (x * cos - y * sin, x * sin + y * cos)
+        };
+        let (x, y) = rotate(&planet["position"]);
+        let (vx, vy) = rotate(&planet["velocity"]);
+        planet["position"] = serde_json::json!({"x": x + self.offset[0], "y": y + self.offset[1]});
+        planet["velocity"] = serde_json::json!({"x": vx, "y": vy});
+        planet
+    }
+}
+
+/// Fields actually written in a config file (not defaulted).
+fn top_level_keys(text: &str, format: ConfigFormat) -> Result<Vec<String>, String> {
+    let keys = match format {
+        ConfigFormat::Json => serde_json::from_str::<serde_json::Value>(text).map_err(|e| e.to_string())?
+            .as_object().map(|map| map.keys().cloned().collect()),
+        ConfigFormat::Toml => toml::from_str::<toml::Value>(text).map_err(|e| e.to_string())?
+            .as_table().map(|table| table.keys().cloned().collect()),
+        ConfigFormat::Yaml => serde_yaml::from_str::<serde_yaml::Value>(text).map_err(|e| e.to_string())?
+            .as_mapping().map(|mapping| mapping.iter().filter_map(|(key, _)| key.as_str().map(String::from)).collect()),
+        ConfigFormat::Ron => match ron::de::from_str::<ron::Value>(text).map_err(|e| e.to_string())? {
+            ron::Value::Map(map) => Some(map.keys().filter_map(|key| match key {
+                ron::Value::String(key) => Some(key.clone()),
+                _ => None,
+            }).collect()),
+            _ => None,
+        },
+    };
+    Ok(keys.unwrap_or_default())
+}
+
+/// x, y vector e.g. {"x": 1.0, "y": 2.0}
+#[derive(Default, Serialize, Deserialize, JsonSchema)]
+struct Xy {
+    x: Scalar,
+    y: Scalar,
+}
+
+/// size e.g. {"width": 512, "height": 512}
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct Size {
+    width: u32,
+    height: u32,
+}
+
+/// float RGBA color e.g. {"r": 1.0, "g": 0.0, "b": 0.0, "a": 0.8}
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct Rgba {
+    r: f64,
+    g: f64,
+    b: f64,
+    a: f64,
+}
+
+/// planet e.g. {"position": {"x": 0.0, "y": 100.0}, "radius": 10.0, "velocity": {"x": 1.0, "y": 0.0}}
+/// version 1 planets e.g. {"x": 0.0, "y": 100.0, "r": 10.0} are read too
+#[derive(Serialize, JsonSchema)]
+struct PlanetConfig {
+    position: Xy,
+    radius: Scalar,
+    /// initial velocity
+    #[serde(default)]
+    velocity: Xy,
+}
+
+impl<'de> Deserialize<'de> for PlanetConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<PlanetConfig, D::Error> {
+        const FIELDS: &[&str] = &["position", "radius", "velocity", "x", "y", "r"];
+        deserializer.deserialize_struct("PlanetConfig", FIELDS, PlanetConfigVisitor)
+    }
+}
+
+/// reads both planet formats
+struct PlanetConfigVisitor;
+
+/// planet keys, version 1 ones included
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum PlanetField {
+    Position,
+    Radius,
+    Velocity,
+    X,
+    Y,
+    R,
+    #[serde(other)]
+    Other,
+}
+
+impl<'de> serde::de::Visitor<'de> for PlanetConfigVisitor {
+    type Value = PlanetConfig;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a planet with position and radius")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<PlanetConfig, A::Error> {
+        use serde::de::Error;
+        let (mut position, mut x, mut y, mut radius, mut velocity) = (None, None, None, None, None);
+        while let Some(key) = map.next_key::<PlanetField>()? {
+            match key {
+                PlanetField::Position => position = Some(map.next_value::<Xy>()?),
+                PlanetField::Radius | PlanetField::R => radius = Some(map.next_value::<Scalar>()?),
+                PlanetField::Velocity => velocity = Some(map.next_value::<Xy>()?),
+                PlanetField::X => x = Some(map.next_value::<Scalar>()?),
+                PlanetField::Y => y = Some(map.next_value::<Scalar>()?),
+                PlanetField::Other => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+        }
+        let position = match (position, x, y) {
+            (Some(position), _, _) => position,
+            (None, Some(x), Some(y)) => Xy { x, y },
+            _ => return Err(A::Error::missing_field("position")),
+        };
+        let radius = radius.ok_or_else(|| A::Error::missing_field("radius"))?;
+        Ok(PlanetConfig { position, radius, velocity: velocity.unwrap_or_default() })
+    }
+}
+
+/// f32 color channel as the shortest f64 e.g. 0.2 rather than 0.20000000298023224
+fn channel(c: f32) -> f64 {
+    c.to_string().parse().unwrap_or_else(|_| f64::from(c))
+}
+
+mod xy {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &Vec2d<Scalar>, serializer: S) -> Result<S::Ok, S::Error> {
+        Xy { x: v[0], y: v[1] }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec2d<Scalar>, D::Error> {
+        let v = Xy::deserialize(deserializer)?;
+        Ok([v.x, v.y])
+    }
+}
+
+mod size {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(size: &[u32; 2], serializer: S) -> Result<S::Ok, S::Error> {
+        Size { width: size[0], height: size[1] }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u32; 2], D::Error> {
+        let size = Size::deserialize(deserializer)?;
+        Ok([size.width, size.height])
+    }
+}
+
+mod rgba {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(c: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        Rgba { r: channel(c[0]), g: channel(c[1]), b: channel(c[2]), a: channel(c[3]) }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let c = Rgba::deserialize(deserializer)?;
+        Ok([c.r as f32, c.g as f32, c.b as f32, c.a as f32])
+    }
+}
+
+mod planets {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(planets: &[Planet], serializer: S) -> Result<S::Ok, S::Error> {
+        let planets: Vec<PlanetConfig> = planets.iter().map(|p| PlanetConfig {
+            position: Xy { x: p.position[0], y: p.position[1] },
+            radius: p.r,
+            velocity: Xy { x: p.velocity[0], y: p.velocity[1] },
+        }).collect();
+        planets.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Planet>, D::Error> {
+        let planets = Vec::<PlanetConfig>::deserialize(deserializer)?;
+        // radii are checked by `Config::validate`
+        Ok(planets.iter().enumerate().map(|(i, p)| {
+            let mut planet = Planet::unchecked(p.position.x, p.position.y, p.radius, i);
+            planet.velocity = [p.velocity.x, p.velocity.y];
+            planet
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_overrides() {
+        let mut json = Config::default().to_json();
+        Config::set(&mut json, "gravity=0.1").unwrap();
+        Config::set(&mut json, "camera_position.x=-50").unwrap();
+        Config::set(&mut json, "colormap=plasma").unwrap();
+        assert!(Config::set(&mut json, "gravityy=0.1").is_err());
+        assert!(Config::set(&mut json, "gravity").is_err());
+        let config = Config::from_value(&json).unwrap();
+        assert_eq!(config.gravity, 0.1);
+        assert_eq!(config.camera_position, [-50., 0.]);
+        assert_eq!(config.colormap, Colormap::Plasma);
+        // round trip
+        let json = config.to_json();
+        assert_eq!(Config::from_value(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn test_validate_reports_every_violation() {
+        assert!(Config::default().validate().is_ok());
+        let mut config = Config::default();
+        config.zoom_factor = 0.5;
+        config.frame_time_step = 0.;
+        config.planet_texture_path = Some(String::from("res/missing.png"));
+        config.planets = vec![Planet::unchecked(0., 0., 10., 0), Planet::unchecked(5., 0., 10., 1), Planet::unchecked(100., 0., -1., 2)];
+        let report = config.validate().unwrap_err();
+        assert_eq!(report.lines().count(), 5);
+        assert!(report.contains("zoom_factor must be > 1.0 (got 0.5)"));
+        assert!(report.contains("planets 0 and 1 overlap"));
+        assert!(report.contains("planet 2: radius must be > 0.0 (got -1)"));
+
+        let mut config = Config::default();
+        config.seed = Some(1);
+        config.planet_radius = 0.;
+        assert_eq!(config.validate().unwrap_err(), "  - planet_radius must be > 0.0 (got 0)");
+
+        // missing HUD font: only a warning
+        let mut config = Config::default();
+        config.hud_font_path = Some(String::from("res/missing.ttf"));
+        assert!(config.validate().is_ok());
+        assert_eq!(config.window_warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_includes() {
+        let directory = std::env::temp_dir().join(format!("galaxy-test-includes-{}", std::process::id()));
+        fs::create_dir_all(directory.join("parts")).unwrap();
+        fs::write(directory.join("parts/physics.toml"), "gravity = 0.2\nsoftening_factor = 0.5\n").unwrap();
+        fs::write(directory.join("parts/pair.json"), r#"{"gravity": 0.3, "planet_texture_path": "earth.png", "planets": [{"x": 10.0, "y": 0.0, "r": 1.0}]}"#).unwrap();
+        fs::write(directory.join("scene.json"), r#"{
+            "include": [{"path": "parts/physics.toml"}, {"path": "parts/pair.json", "offset": {"x": 100.0, "y": 0.0}, "rotation": 90.0}],
+            "softening_factor": 0.1,
+            "planets": [{"x": 0.0, "y": 0.0, "r": 2.0}]
+        }"#).unwrap();
+        let config = Config::try_load(&directory.join("scene.json"), &[]).unwrap();
+        fs::remove_dir_all(&directory).ok();
+        // later files override earlier ones
+        assert_eq!(config.gravity, 0.3);
+        assert_eq!(config.softening_factor, 0.1);
+        // paths are relative to the file setting them
+        assert_eq!(config.planet_texture_path.map(PathBuf::from), Some(directory.join("parts").join("earth.png")));
+        assert_eq!(config.hud_font_path, Config::default().hud_font_path);
+        assert_eq!(config.restitution_factor, Config::default().restitution_factor);
+        // included planets first, rotated then moved
+        assert_eq!(config.planets.len(), 2);
+        assert!((config.planets[0].position[0] - 100.).abs() < 1e-9);
+        assert!((config.planets[0].position[1] - 10.).abs() < 1e-9);
+        assert_eq!(config.planets[1].r, 2.);
+    }
+
+    #[test]
+    fn test_committed_schema_is_up_to_date() {
+        let committed: serde_json::Value = serde_json::from_str(include_str!("../res/config.schema")).unwrap();
+        let generated: serde_json::Value = serde_json::from_str(&Config::schema()).unwrap();
+        assert!(committed == generated, "res/config.schema is outdated: cargo run -- --dump-schema > res/config.schema");
+    }
+
+    #[test]
+    fn test_formats_share_fields_and_defaults() {
+        let json = r#"{"gravity": 0.1, "default_zom": 2.0, "camera_position": {"x": -50.0, "y": 0.0},
+            "planet_color": {"r": 1.0, "g": 0.0, "b": 0.0, "a": 1.0}, "planets": [{"x": 0.0, "y": 100.0, "r": 10.0}], "colormap": "plasma"}"#;
+        let toml = "gravity = 0.1\ndefault_zoom = 2.0\ncolormap = \"plasma\"\ncamera_position = { x = -50.0, y = 0.0 }\n\
+            planet_color = { r = 1.0, g = 0.0, b = 0.0, a = 1.0 }\nplanets = [{ x = 0.0, y = 100.0, r = 10.0 }]\n";
+        let yaml = "gravity: 0.1\ndefault_zoom: 2.0\ncolormap: plasma\ncamera_position: {x: -50.0, y: 0.0}\n\
+            planet_color: {r: 1.0, g: 0.0, b: 0.0, a: 1.0}\nplanets:\n  - {x: 0.0, y: 100.0, r: 10.0}\n";
+        let ron = "(gravity: 0.1, default_zoom: 2.0, colormap: plasma, camera_position: (x: -50.0, y: 0.0),\
+            planet_color: (r: 1.0, g: 0.0, b: 0.0, a: 1.0), planets: [(x: 0.0, y: 100.0, r: 10.0)])";
+        let expected = Config::parse(json, ConfigFormat::Json).unwrap().to_json();
+        assert_eq!(expected["default_zoom"], 2.0);
+        assert_eq!(expected["softening_factor"], Config::default().to_json()["softening_factor"]);
+        for (text, format) in [(toml, ConfigFormat::Toml), (yaml, ConfigFormat::Yaml), (ron, ConfigFormat::Ron)].iter() {
+            assert_eq!(Config::parse(text, *format).unwrap().to_json(), expected, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_migrate_file() {
+        let directory = std::env::temp_dir().join(format!("galaxy-test-migrate-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let files = [
+            ("old.json", r#"{"gravity": 0.1, "default_zom": 2.0, "colour": "red", "planets": [{"x": 0.0, "y": 100.0, "r": 10.0}]}"#),
+            ("old.toml", "gravity = 0.1\ndefault_zom = 2.0\ncolour = \"red\"\nplanets = [{ x = 0.0, y = 100.0, r = 10.0 }]\n"),
+            ("old.yaml", "gravity: 0.1\ndefault_zom: 2.0\ncolour: red\nplanets:\n  - {x: 0.0, y: 100.0, r: 10.0}\n"),
+            ("old.ron", "(gravity: 0.1, default_zom: 2.0, colour: \"red\", planets: [(x: 0.0, y: 100.0, r: 10.0)])"),
+        ];
+        for (name, text) in files.iter() {
+            let path = directory.join(name);
+            fs::write(&path, text).unwrap();
+            let loaded = Config::try_load(&path, &[]).unwrap();
+            let warnings = Config::migrate_file(&path).unwrap();
+            assert_eq!(warnings.len(), 3, "{}: {:?}", name, warnings);
+            let migrated_text = fs::read_to_string(&path).unwrap();
+            let migrated = Config::parse(&migrated_text, ConfigFormat::from_path(&path)).unwrap();
+            assert_eq!(migrated.version, CONFIG_VERSION);
+            assert_eq!(migrated.to_json(), loaded.to_json(), "{}", name);
+            assert!(migrated_text.contains("position") && !migrated_text.contains("default_zom"), "{}", name);
+            // latest version: nothing to report
+            assert!(Config::migrate_file(&path).unwrap().is_empty(), "{}", name);
+        }
+        fs::remove_dir_all(&directory).ok();
+    }
+}
@@ -0,0 +1,115 @@
+//! Config watcher: reloads the config file when it or one of its includes changes on disk
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::Config;
+
+/// the file modification time is checked at most this often
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls the modification times of the config file and its includes
+pub struct ConfigWatcher {
+    /// watched config file
+    pub path: PathBuf,
+    /// overrides applied on reload (see `Config::set`)
+    pub overrides: Vec<String>,
+    /// files read by the last load (path and its includes) and their last seen modification times
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    /// last poll
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    /// Creates a new config watcher, the current file content is considered already loaded.
+    pub fn new(path: PathBuf, overrides: Vec<String>) -> ConfigWatcher {
+        let mut watcher = ConfigWatcher {
+            path,
+            overrides,
+            files: vec![],
+            last_poll: Instant::now(),
+        };
+        let mut files = vec![];
+        Config::try_load_files(&watcher.path, &watcher.overrides, &mut files).ok();
+        watcher.watch(files);
+        watcher
+    }
+
+    /// files whose modification times are polled
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+
+    /// Reloaded config if the file or one of its includes changed since the last poll.
+    /// Invalid files (see `Config::validate`) are reported and skipped: the next change is tried again.
+    pub fn poll(&mut self) -> Option<Config> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        self.reload_changed()
+    }
+
+    /// reloads the config if a watched file changed, ignoring the poll interval
+    fn reload_changed(&mut self) -> Option<Config> {
+        let changed = self.files.iter().any(|(path, last_modified)| {
+            let modified = modified(path);
+            modified.is_some() && modified != *last_modified
+        });
+        if !changed {
+            return None;
+        }
+        // includes may have been added or removed
+        let mut files = vec![];
+        let config = Config::try_load_files(&self.path, &self.overrides, &mut files);
+        self.watch(files);
+        match config.and_then(|config| config.validate().map(|_| config)) {
+            Ok(config) => {
+                println!("reloaded {}", self.path.to_string_lossy());
+                Some(config)
+            }
+            Err(e) => {
+                println!("could not reload {}:\n{}", self.path.to_string_lossy(), e);
+                None
+            }
+        }
+    }
+
+    /// watches files, their current content is considered already loaded
+    fn watch(&mut self, files: Vec<PathBuf>) {
+        self.files = files.into_iter().map(|path| {
+            let modified = modified(&path);
+            (path, modified)
+        }).collect();
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes_are_watched() {
+        let directory = std::env::temp_dir().join(format!("galaxy-test-watcher-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("physics.json"), r#"{"gravity": 0.2}"#).unwrap();
+        fs::write(directory.join("scene.json"), r#"{"include": [{"path": "physics.json"}]}"#).unwrap();
+        let mut watcher = ConfigWatcher::new(directory.join("scene.json"), vec![]);
+        assert_eq!(watcher.files().count(), 2);
+        assert!(watcher.reload_changed().is_none());
+
+        fs::write(directory.join("physics.json"), r#"{"gravity": 0.3}"#).unwrap();
+        // mtime resolution may be coarse
+        fs::File::options().write(true).open(directory.join("physics.json")).unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        let config = watcher.reload_changed();
+        fs::remove_dir_all(&directory).ok();
+        assert_eq!(config.map(|config| config.gravity), Some(0.3));
+        assert!(watcher.reload_changed().is_none());
+    }
+}
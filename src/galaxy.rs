@@ -1,8 +1,10 @@
-use graphics::math::{Scalar, Vec2d};
+use graphics::math::{Scalar, square_len, sub, Vec2d};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 use crate::config::Config;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct Planet {
     /// x, y position
     pub position: Vec2d<Scalar>,
@@ -18,7 +20,8 @@ pub struct Planet {
     pub imass: Scalar,
     /// unique id
     pub id: usize,
-
+    /// number of collision impulses received
+    pub collisions: u32,
 }
 
 impl Planet {
@@ -26,6 +29,12 @@ impl Planet {
     /// radius must be > 0.0
     pub fn default(x: Scalar, y: Scalar, r: Scalar, id: usize) -> Planet {
         assert!(r > 0.);
+        Planet::unchecked(x, y, r, id)
+    }
+
+    /// create a new planet without checking its radius
+    /// e.g. config planets, checked by `Config::validate`
+    pub fn unchecked(x: Scalar, y: Scalar, r: Scalar, id: usize) -> Planet {
         let mass = r * r;
         Planet {
             position: [x, y],
@@ -35,6 +44,7 @@ impl Planet {
             mass,
             imass: 1. / mass,
             id,
+            collisions: 0,
         }
     }
 }
@@ -51,10 +61,38 @@ impl Galaxy {
         Galaxy::from_config(&Config::default())
     }
 
-    /// Creates a new galaxy from config.
+    /// Creates a new galaxy from config, random planets if config has a seed.
     pub fn from_config(config: &Config) -> Galaxy {
+        let planets = match config.seed {
+            Some(seed) => Galaxy::random_planets(seed, config.random_planet_count, config.random_scene_radius, config.planet_radius),
+            None => config.planets.clone(),
+        };
         Galaxy {
-            planets: config.planets.clone()
+            planets
+        }
+    }
+
+    /// count random planets at rest in a disc of scene_radius, radii in [planet_radius / 2, 3 * planet_radius / 2]
+    /// the same seed gives the same planets, planets do not overlap (unless the disc is too crowded)
+    pub fn random_planets(seed: u64, count: usize, scene_radius: Scalar, planet_radius: Scalar) -> Vec<Planet> {
+        const MAX_TRIES: usize = 100;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut planets: Vec<Planet> = vec![];
+        for id in 0..count {
+            let r = rng.gen_range(0.5, 1.5) * planet_radius;
+            let mut planet = Planet::default(0., 0., r, id);
+            for _ in 0..MAX_TRIES {
+                // uniform in the disc
+                let distance = rng.gen::<Scalar>().sqrt() * scene_radius;
+                let angle = rng.gen_range(0., 2. * std::f64::consts::PI);
+                planet.position = [distance * angle.cos(), distance * angle.sin()];
+                let overlaps = planets.iter().any(|p| square_len(sub(p.position, planet.position)) < (p.r + r) * (p.r + r));
+                if !overlaps {
+                    break;
+                }
+            }
+            planets.push(planet);
         }
+        planets
     }
 }
\ No newline at end of file
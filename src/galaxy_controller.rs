@@ -1,19 +1,46 @@
 // ! galaxy controller (handles event)
 
+use std::collections::VecDeque;
+
 use graphics::math::{add, dot, mul_scalar, Scalar, square_len, sub, Vec2d};
 use piston::input::{Button, Key, MouseButton};
 use piston::input::GenericEvent;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use crate::camera::Camera;
 use crate::config::Config;
 use crate::Galaxy;
 use crate::galaxy::Planet;
+use crate::barnes_hut::QuadTree;
+use crate::gravity_kernel::{GravitySolver, PlanetArrays};
+use crate::softening::SofteningKernel;
+
+/// impacts resolved per step at most (e.g. resting planets bouncing back and forth),
+/// raised to `MAX_IMPACTS_PER_PLANET` per planet in large galaxies
+const MAX_IMPACTS_PER_STEP: usize = 256;
+/// impacts resolved per step and planet at most
+const MAX_IMPACTS_PER_PLANET: usize = 4;
 
 pub struct GalaxySettings {
     pub gravity: Scalar,
     pub softening_factor: Scalar,
+    /// force law between close planets
+    pub softening_kernel: SofteningKernel,
+    /// direct or Barnes–Hut gravity
+    pub gravity_solver: GravitySolver,
+    /// Barnes–Hut opening angle
+    pub barnes_hut_theta: Scalar,
     pub restitution_factor: Scalar,
     pub planet_radius: Scalar,
+    /// number of past positions kept per planet
+    pub trail_length: usize,
+    /// subdivide update steps (see `adaptive_time_step`)
+    pub adaptive_time_step: bool,
+    /// adaptive step = time_step_accuracy * shortest planet time scale
+    pub time_step_accuracy: Scalar,
+    /// adaptive steps are never shorter than this
+    pub min_time_step: Scalar,
 }
 
 impl GalaxySettings {
@@ -21,8 +48,39 @@ impl GalaxySettings {
         GalaxySettings {
             gravity: config.gravity,
             softening_factor: config.softening_factor,
+            softening_kernel: config.softening_kernel,
+            gravity_solver: config.gravity_solver,
+            barnes_hut_theta: config.barnes_hut_theta,
             restitution_factor: config.restitution_factor,
             planet_radius: config.planet_radius,
+            trail_length: config.trail_length,
+            adaptive_time_step: config.adaptive_time_step,
+            time_step_accuracy: config.time_step_accuracy,
+            min_time_step: config.min_time_step,
+        }
+    }
+}
+
+/// A collision resolved during the last impulse pass
+#[derive(Clone, Copy)]
+pub struct Contact {
+    /// contact point
+    pub position: Vec2d<Scalar>,
+    /// collision normal (unit vector, from the first planet to the second one)
+    pub normal: Vec2d<Scalar>,
+    /// impulse applied to the first planet
+    pub impulse: Vec2d<Scalar>,
+}
+
+impl Contact {
+    fn new(planet: &Planet, other_planet: &Planet, impulse: Vec2d<Scalar>) -> Contact {
+        let delta = sub(other_planet.position, planet.position);
+        let distance = square_len(delta).sqrt();
+        let normal = if distance > 0. { mul_scalar(delta, 1. / distance) } else { [0., 0.] };
+        Contact {
+            position: add(planet.position, mul_scalar(delta, planet.r / (planet.r + other_planet.r))),
+            normal,
+            impulse,
         }
     }
 }
@@ -37,12 +95,178 @@ pub struct GalaxyController {
     pub settings: GalaxySettings,
     /// mouse cursor position
     pub cursor: Vec2d<Scalar>,
+    /// net gravitational acceleration of each planet (last update)
+    pub accelerations: Vec<Vec2d<Scalar>>,
+    /// planet positions and masses mirrored for the gravity kernel
+    arrays: PlanetArrays,
+    /// quadtree of the Barnes–Hut solver
+    tree: QuadTree,
+    /// collisions resolved during the last update (every substep)
+    pub contacts: Vec<Contact>,
+    /// past positions of each planet, oldest first
+    pub trails: Vec<VecDeque<Vec2d<Scalar>>>,
+    /// total simulated time
+    pub simulated_time: Scalar,
+    /// last time step used by `update`
+    pub last_dt: Scalar,
+    /// number of steps the last update was subdivided in
+    pub substeps: usize,
+    /// planet positions before the last update (render interpolation)
+    pub previous_positions: Vec<Vec2d<Scalar>>,
+    /// planets are drawn at previous + render_alpha * (current - previous) positions
+    /// 1.0: current positions
+    pub render_alpha: Scalar,
+    /// total energy the energy drift is measured against
+    /// reset whenever planets are added
+    pub reference_energy: Scalar,
+    /// planets restored by `reset`
+    pub initial_planets: Vec<Planet>,
+    /// the simulation does not advance while paused
+    pub paused: bool,
+    /// failed health check of the last update (see `check_health`), the simulation is then paused
+    pub error: Option<String>,
+    /// impacts left unresolved because a step hit its impact cap (see `MAX_IMPACTS_PER_STEP`), since the last reset
+    pub dropped_impacts: usize,
 }
 
 #[cfg(test)]
 mod tests {
+    use std::f64::consts::PI;
+
+    use proptest::prelude::*;
     use super::*;
 
+    /// two planets, no softening (almost) nor adaptive step so that closed-form results apply
+    fn two_bodies(p0: Planet, p1: Planet, gravity: Scalar) -> GalaxyController {
+        let mut config = Config::default();
+        config.gravity = gravity;
+        config.softening_factor = 1e-9;
+        config.adaptive_time_step = false;
+        config.planets = vec![p0, p1];
+        let mut controller = GalaxyController::from_config(&config);
+        controller.galaxy.planets = vec![p0, p1];
+        controller
+    }
+
+    /// planet at (x, 0) moving at (0, vy)
+    fn moving_planet(x: Scalar, vy: Scalar, r: Scalar, id: usize) -> Planet {
+        let mut planet = Planet::default(x, 0., r, id);
+        planet.velocity = [0., vy];
+        planet
+    }
+
+    /// two bodies at distance apart on the x axis, center of mass at rest at the origin,
+    /// relative speed speed along y
+    fn orbit(distance: Scalar, speed: Scalar, gravity: Scalar) -> (GalaxyController, Scalar) {
+        let (m0, m1) = (25., 1.);
+        let total = m0 + m1;
+        let p0 = moving_planet(-distance * m1 / total, -speed * m1 / total, 5., 0);
+        let p1 = moving_planet(distance * m0 / total, speed * m0 / total, 1., 1);
+        (two_bodies(p0, p1, gravity), gravity * total)
+    }
+
+    /// relative position and velocity of planet 1 w.r.t planet 0
+    fn relative(controller: &GalaxyController) -> (Vec2d<Scalar>, Vec2d<Scalar>) {
+        let planets = &controller.galaxy.planets;
+        (sub(planets[1].position, planets[0].position), sub(planets[1].velocity, planets[0].velocity))
+    }
+
+    /// eccentricity of the relative orbit (mu = G.M)
+    fn eccentricity(controller: &GalaxyController, mu: Scalar) -> Scalar {
+        let (r, v) = relative(controller);
+        let h = r[0] * v[1] - r[1] * v[0];
+        let distance = square_len(r).sqrt();
+        let e = [v[1] * h / mu - r[0] / distance, -v[0] * h / mu - r[1] / distance];
+        square_len(e).sqrt()
+    }
+
+    #[test]
+    fn test_circular_orbit_keeps_radius_and_period() {
+        let (distance, gravity) = (100., 1.);
+        let mu = gravity * 26.;
+        let (mut controller, _) = orbit(distance, (mu / distance).sqrt(), gravity);
+        let period = 2. * PI * (distance * distance * distance / mu).sqrt();
+        let steps = 4000;
+        for _ in 0..steps {
+            controller.update(period / steps as Scalar);
+            let radius = square_len(relative(&controller).0).sqrt();
+            assert!((radius - distance).abs() < 0.001 * distance, "radius {}", radius);
+        }
+        // one period later, back to the starting point
+        let (r, _) = relative(&controller);
+        assert!(r[1].atan2(r[0]).abs() < 0.001, "angle after one period {}", r[1].atan2(r[0]));
+    }
+
+    #[test]
+    fn test_elliptic_orbit_keeps_eccentricity() {
+        // from apoapsis at 0.8 times the circular speed: e = 1 - 0.8^2
+        let (distance, gravity) = (100., 1.);
+        let mu = gravity * 26.;
+        let (mut controller, _) = orbit(distance, 0.8 * (mu / distance).sqrt(), gravity);
+        let expected = 1. - 0.8 * 0.8;
+        assert!((eccentricity(&controller, mu) - expected).abs() < 1e-6);
+        let semi_major_axis = distance / (1. + expected);
+        let period = 2. * PI * (semi_major_axis * semi_major_axis * semi_major_axis / mu).sqrt();
+        let steps = 10000;
+        for _ in 0..2 * steps {
+            controller.update(period / steps as Scalar);
+            let e = eccentricity(&controller, mu);
+            assert!((e - expected).abs() < 0.001, "eccentricity {}", e);
+        }
+        assert!(controller.contacts.is_empty());
+    }
+
+    #[test]
+    fn test_head_on_collision_matches_restitution() {
+        // 1D: v0' = (m0.u0 + m1.u1 + m1.e.(u1 - u0)) / (m0 + m1), v1' = (m0.u0 + m1.u1 + m0.e.(u0 - u1)) / (m0 + m1)
+        let (u0, u1) = (3., -1.);
+        let mut p0 = Planet::default(0., 0., 2., 0);
+        let mut p1 = Planet::default(10., 0., 1., 1);
+        p0.velocity = [u0, 0.];
+        p1.velocity = [u1, 0.];
+        let mut controller = two_bodies(p0, p1, 0.);
+        let e = 0.5;
+        controller.settings.restitution_factor = e;
+        let (m0, m1) = (p0.mass, p1.mass);
+        let momentum = m0 * u0 + m1 * u1;
+        let v0 = (momentum + m1 * e * (u1 - u0)) / (m0 + m1);
+        let v1 = (momentum + m0 * e * (u0 - u1)) / (m0 + m1);
+
+        // swept collision, at impact time
+        controller.update(5.);
+        let planets = &controller.galaxy.planets;
+        assert_eq!(controller.contacts.len(), 1);
+        assert!((planets[0].velocity[0] - v0).abs() < 1e-9 && (planets[1].velocity[0] - v1).abs() < 1e-9);
+
+        // overlapping planets, discrete impulses
+        p1.position = [2.5, 0.];
+        let mut controller = two_bodies(p0, p1, 0.);
+        controller.settings.restitution_factor = e;
+        controller.compute_impulses();
+        let planets = &controller.galaxy.planets;
+        assert!((u0 + planets[0].impulse[0] - v0).abs() < 1e-6 && (u1 + planets[1].impulse[0] - v1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_free_fall_time_matches_kepler() {
+        // radial Kepler orbit of semi-major axis D / 2: r = a.(1 - cos(n)), t = sqrt(a^3 / mu).(pi - n + sin(n))
+        let (distance, gravity) = (100., 1.);
+        let p0 = Planet::default(0., 0., 5., 0);
+        let p1 = Planet::default(distance, 0., 1., 1);
+        let mu = gravity * (p0.mass + p1.mass);
+        let a = distance / 2.;
+        let n = (1. - (p0.r + p1.r) / a).acos();
+        let expected = (a * a * a / mu).sqrt() * (PI - n + n.sin());
+
+        let mut controller = two_bodies(p0, p1, gravity);
+        let dt = expected / 10000.;
+        while controller.contacts.is_empty() {
+            controller.update(dt);
+            assert!(controller.simulated_time < 2. * expected, "no contact");
+        }
+        assert!((controller.simulated_time - expected).abs() < 0.001 * expected, "contact at {}, expected {}", controller.simulated_time, expected);
+    }
+
     #[test]
     fn test_intersect() {
         let p0 = Planet::default(0., 0., 2., 0);
@@ -53,6 +277,335 @@ mod tests {
         assert!(intersect(&p1, &p2));
         assert!(!intersect(&p0, &p3));
     }
+
+    /// cargo test --features parallel
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_map_planets_matches_serial() {
+        let planets = Galaxy::random_planets(1, 500, 300., 10.);
+        let force = |(_, planet): (usize, &Planet)| GalaxyController::compute_gravitational_force(planet, &planets, 0.05, SofteningKernel::Plummer, 0.01);
+        let serial: Vec<Vec2d<Scalar>> = planets.iter().enumerate().map(force).collect();
+        let parallel: Vec<Vec2d<Scalar>> = planets.par_iter().enumerate().map(force).collect();
+        // identical, in planet order
+        assert_eq!(parallel, serial);
+        assert_eq!(map_planets(&planets, force), serial);
+    }
+
+    #[test]
+    fn test_barnes_hut_solver() {
+        let mut config = Config::default();
+        config.planets = Galaxy::random_planets(5, 300, 500., 10.);
+        let mut controller = GalaxyController::from_config(&config);
+        controller.compute_accelerations();
+        let direct = controller.accelerations.clone();
+        controller.settings.gravity_solver = GravitySolver::BarnesHut;
+        controller.settings.barnes_hut_theta = 0.;
+        controller.compute_accelerations();
+        for (acceleration, expected) in controller.accelerations.iter().zip(direct.iter()) {
+            assert!(square_len(sub(*acceleration, *expected)).sqrt() <= 1e-12 * square_len(*expected).sqrt());
+        }
+    }
+
+    #[test]
+    fn test_gravity_kernel_matches_reference() {
+        let planets = Galaxy::random_planets(2, 203, 300., 10.);
+        let mut arrays = PlanetArrays::new();
+        arrays.update(&planets);
+        // softening length 5: the cubic spline kernel is not Newtonian for close planets
+        let kernels = [SofteningKernel::None, SofteningKernel::Plummer, SofteningKernel::CubicSpline];
+        for (kernel, planet) in kernels.iter().flat_map(|kernel| planets.iter().map(move |planet| (*kernel, planet))) {
+            let expected = GalaxyController::compute_gravitational_force(planet, &planets, 0.05, kernel, 25.);
+            let acceleration = arrays.acceleration(planet.position, 0.05, kernel, 25.);
+            // same terms, summed in another order
+            assert!(square_len(sub(acceleration, expected)).sqrt() <= 1e-12 * square_len(expected).sqrt());
+        }
+    }
+
+    #[test]
+    fn test_broadphase_finds_every_impact() {
+        let mut config = Config::default();
+        config.planets = Galaxy::random_planets(3, 300, 400., 10.);
+        let mut controller = GalaxyController::from_config(&config);
+        for (i, planet) in controller.galaxy.planets.iter_mut().enumerate() {
+            let angle = i as Scalar;
+            planet.velocity = [50. * angle.cos(), 50. * angle.sin()];
+        }
+        let dt = 1.;
+        let planets = &controller.galaxy.planets;
+        let mut expected = vec![];
+        for i in 0..planets.len() {
+            for j in i + 1..planets.len() {
+                if let Some(t) = time_of_impact(&planets[i], &planets[j]).filter(|t| *t <= dt) {
+                    expected.push((i, j, t));
+                }
+            }
+        }
+        let mut impacts = controller.broadphase_impacts(dt);
+        impacts.sort_by_key(|(i, j, _)| (*i, *j));
+        assert!(!expected.is_empty());
+        assert_eq!(impacts, expected);
+        let with_first: Vec<_> = expected.iter().cloned().filter(|(i, j, _)| *i == 0 || *j == 0).collect();
+        assert_eq!(controller.impacts_with(0, dt), with_first);
+    }
+
+    #[test]
+    fn test_dropped_impacts_are_counted() {
+        // a light planet bouncing between two heavy ones closing in: the bounces get ever more frequent
+        let mut config = Config::default();
+        config.gravity = 0.;
+        config.restitution_factor = 1.;
+        config.planets = vec![Planet::default(-1001., 0., 1000., 0), Planet::default(0., 0., 0.1, 1), Planet::default(1001., 0., 1000., 2)];
+        config.planets[0].velocity = [1., 0.];
+        config.planets[2].velocity = [-1., 0.];
+        config.planets[1].velocity = [1000., 0.];
+        let mut controller = GalaxyController::from_config(&config);
+        controller.compute_positions(0.89);
+        assert!(controller.dropped_impacts > 0);
+        assert_eq!(controller.contacts.len(), MAX_IMPACTS_PER_STEP);
+        controller.reset();
+        assert_eq!(controller.dropped_impacts, 0);
+    }
+
+    #[test]
+    fn test_time_of_impact() {
+        let mut p0 = Planet::default(0., 0., 1., 0);
+        let p1 = Planet::default(10., 0., 1., 1);
+        assert_eq!(time_of_impact(&p0, &p1), None);
+        p0.velocity = [4., 0.];
+        assert_eq!(time_of_impact(&p0, &p1), Some(2.));
+        p0.velocity = [-4., 0.];
+        assert_eq!(time_of_impact(&p0, &p1), None);
+        p0.velocity = [4., 4.];
+        assert_eq!(time_of_impact(&p0, &p1), None);
+    }
+
+    #[test]
+    fn test_fast_planets_do_not_tunnel() {
+        // head-on collision, result does not depend on the time step
+        let mut config = Config::default();
+        config.gravity = 0.;
+        config.adaptive_time_step = false;
+        config.restitution_factor = 1.;
+        config.planets = vec![Planet::default(0., 0., 1., 0), Planet::default(10., 0., 1., 1)];
+        let run = |dt: Scalar, steps: usize| {
+            let mut controller = GalaxyController::from_config(&config);
+            controller.galaxy.planets[0].velocity = [40., 0.];
+            for _ in 0..steps {
+                controller.update(dt);
+            }
+            controller.galaxy.planets
+        };
+        let (coarse, fine) = (run(1., 1), run(0.01, 100));
+        for (planet, fine_planet) in coarse.iter().zip(fine.iter()) {
+            assert_eq!(planet.collisions, 1);
+            assert!(square_len(sub(planet.position, fine_planet.position)) < 1e-9);
+        }
+        // elastic: velocities exchanged (equal masses), no tunnelling
+        assert!((coarse[1].velocity[0] - 40.).abs() < 1e-9);
+        assert!(coarse[0].position[0] < coarse[1].position[0]);
+    }
+
+    #[test]
+    fn test_potential_energy_matches_force() {
+        // force == -dU/dx, checked with a central difference: gravity and masses are applied consistently
+        // (each kernel's force and potential are checked in softening::tests)
+        let (gravity, softening_factor, h) = (0.05, 0.01, 1e-4);
+        let p0 = Planet::default(0., 0., 2., 0);
+        let p1 = Planet::default(3., 0., 1., 1);
+        let (mut p1_left, mut p1_right) = (p1, p1);
+        p1_left.position[0] -= h;
+        p1_right.position[0] += h;
+        let kernel = SofteningKernel::Plummer;
+        let du = GalaxyController::compute_single_potential_energy(&p0, &p1_right, gravity, kernel, softening_factor)
+            - GalaxyController::compute_single_potential_energy(&p0, &p1_left, gravity, kernel, softening_factor);
+        let force = GalaxyController::compute_single_gravitational_force(&p1, &p0, gravity, kernel, softening_factor)[0] * p1.mass;
+        assert!((force + du / (2. * h)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_apply_config_keeps_planets() {
+        let previous = Config::default();
+        let mut controller = GalaxyController::from_config(&previous);
+        controller.camera.position = [10., 10.];
+        controller.update(previous.frame_time_step);
+        let positions: Vec<Vec2d<Scalar>> = controller.galaxy.planets.iter().map(|p| p.position).collect();
+
+        let mut config = Config::default();
+        config.gravity = 1.;
+        config.planets.truncate(1);
+        controller.apply_config(&config, &previous);
+        assert_eq!(controller.settings.gravity, 1.);
+        assert_eq!(controller.camera.position, [10., 10.]);
+        assert!(controller.galaxy.planets.iter().map(|p| p.position).eq(positions.into_iter()));
+
+        controller.reset();
+        assert_eq!(controller.galaxy.planets.len(), 1);
+    }
+
+    #[test]
+    fn test_adaptive_time_step_subdivides_close_encounters() {
+        let mut config = Config::default();
+        config.planets = vec![Planet::default(0., 0., 1., 0), Planet::default(300., 0., 1., 1)];
+        let mut controller = GalaxyController::from_config(&config);
+        controller.update(config.frame_time_step);
+        // quiet phase: one step per update
+        assert_eq!(controller.substeps, 1);
+
+        controller.galaxy.planets[1].position = [2.5, 0.];
+        controller.galaxy.planets[1].velocity = [0., 5.];
+        controller.update(config.frame_time_step);
+        assert!(controller.substeps > 1);
+        assert!(controller.substeps as Scalar <= (config.frame_time_step / config.min_time_step).ceil());
+        // still in sync with the frame time step
+        assert!((controller.simulated_time - 2. * config.frame_time_step).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_health_check_reports_non_finite_planets() {
+        let mut config = Config::default();
+        config.planets = vec![Planet::default(0., 0., 1., 0), Planet::default(0., 0., 1., 1), Planet::default(50., 0., 1., 2)];
+        let mut controller = GalaxyController::from_config(&config);
+        // coincident planets
+        controller.update(config.frame_time_step);
+        assert_eq!(controller.check_health(), Ok(()));
+        assert!(controller.error.is_none());
+
+        controller.galaxy.planets[2].velocity = [Scalar::NAN, 0.];
+        let error = controller.check_health().unwrap_err();
+        assert!(error.contains("planet 2") && !error.contains("planet 0") && !error.contains("planet 1"));
+
+        // position overflowing within the next step
+        controller.settings.adaptive_time_step = false;
+        controller.galaxy.planets[2].position = [Scalar::MAX, 0.];
+        controller.galaxy.planets[2].velocity = [Scalar::MAX, 0.];
+        let simulated_time = controller.simulated_time;
+        controller.update(config.frame_time_step);
+        assert!(controller.error.as_ref().unwrap().contains("planet 2"));
+        assert!(controller.paused);
+        // last state before the failing step, accelerations included
+        assert_eq!(controller.simulated_time, simulated_time);
+        assert_eq!(controller.galaxy.planets[2].position, [Scalar::MAX, 0.]);
+        assert_eq!(controller.check_health(), Ok(()));
+    }
+
+    /// random planets: position, radius, velocity
+    fn planets_strategy() -> impl Strategy<Value = Vec<Planet>> {
+        let planet = (-200. ..200., -200. ..200., 0.5..20., -10. ..10., -10. ..10.);
+        prop::collection::vec(planet, 1..20).prop_map(|planets| {
+            planets.into_iter().enumerate().map(|(id, (x, y, r, vx, vy))| {
+                let mut planet = Planet::default(x, y, r, id);
+                planet.velocity = [vx, vy];
+                planet
+            }).collect()
+        })
+    }
+
+    /// random galaxy settings and planets
+    #[derive(Debug)]
+    struct RandomGalaxy {
+        planets: Vec<Planet>,
+        gravity: Scalar,
+        softening_kernel: SofteningKernel,
+        softening_factor: Scalar,
+        restitution_factor: Scalar,
+    }
+
+    impl RandomGalaxy {
+        fn config(&self) -> Config {
+            let mut config = Config::default();
+            config.gravity = self.gravity;
+            config.softening_kernel = self.softening_kernel;
+            config.softening_factor = self.softening_factor;
+            config.restitution_factor = self.restitution_factor;
+            config.planets = self.planets.clone();
+            config
+        }
+    }
+
+    /// random galaxy, some planets moved onto the first one (coincident planets)
+    fn galaxy_strategy() -> impl Strategy<Value = RandomGalaxy> {
+        let softening_kernel = prop_oneof![Just(SofteningKernel::None), Just(SofteningKernel::Plummer), Just(SofteningKernel::CubicSpline)];
+        (planets_strategy(), prop::collection::vec(any::<prop::sample::Index>(), 0..3), 0. ..1., softening_kernel, 1e-3..1., 0. ..1.)
+            .prop_map(|(mut planets, coincident, gravity, softening_kernel, softening_factor, restitution_factor)| {
+                for index in coincident {
+                    let i = index.index(planets.len());
+                    planets[i].position = planets[0].position;
+                }
+                RandomGalaxy { planets, gravity, softening_kernel, softening_factor, restitution_factor }
+            })
+    }
+
+    fn momentum(planets: &[Planet]) -> Vec2d<Scalar> {
+        planets.iter().fold([0., 0.], |acc, p| add(acc, mul_scalar(p.velocity, p.mass)))
+    }
+
+    /// sum of momentum magnitudes (scale of rounding errors)
+    fn momentum_scale(planets: &[Planet]) -> Scalar {
+        planets.iter().map(|p| p.mass * square_len(p.velocity).sqrt()).sum()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_update_conserves_momentum(galaxy in galaxy_strategy()) {
+            let config = galaxy.config();
+            let mut controller = GalaxyController::from_config(&config);
+            let before = momentum(&controller.galaxy.planets);
+            let before_scale = momentum_scale(&controller.galaxy.planets);
+            controller.update(config.frame_time_step);
+            let after = momentum(&controller.galaxy.planets);
+            let tolerance = 1e-9 * (1. + before_scale + momentum_scale(&controller.galaxy.planets));
+            prop_assert!(square_len(sub(after, before)).sqrt() <= tolerance, "{:?} != {:?}", after, before);
+        }
+
+        #[test]
+        fn prop_impulses_are_equal_and_opposite(galaxy in galaxy_strategy()) {
+            let planets = &galaxy.planets;
+            for planet in planets.iter() {
+                for other_planet in planets.iter() {
+                    let impulse = GalaxyController::compute_single_impulse(planet, other_planet, galaxy.restitution_factor, galaxy.softening_factor);
+                    let reaction = GalaxyController::compute_single_impulse(other_planet, planet, galaxy.restitution_factor, galaxy.softening_factor);
+                    let (momentum, reaction) = (mul_scalar(impulse, planet.mass), mul_scalar(reaction, other_planet.mass));
+                    let tolerance = 1e-9 * (1. + square_len(momentum).sqrt());
+                    prop_assert!(square_len(add(momentum, reaction)).sqrt() <= tolerance, "{:?} != -{:?}", momentum, reaction);
+                }
+            }
+        }
+
+        #[test]
+        fn prop_intersect_is_symmetric_and_matches_distance(galaxy in galaxy_strategy()) {
+            let planets = &galaxy.planets;
+            for planet in planets.iter() {
+                for other_planet in planets.iter() {
+                    let intersects = intersect(planet, other_planet);
+                    prop_assert_eq!(intersects, intersect(other_planet, planet));
+                    let distance = square_len(sub(other_planet.position, planet.position)).sqrt();
+                    let sum_radii = planet.r + other_planet.r;
+                    // both checks may only disagree because of rounding, when discs are (almost) tangent
+                    if (distance - sum_radii).abs() > 1e-9 * sum_radii {
+                        prop_assert_eq!(intersects, distance <= sum_radii);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn prop_coincident_planets_stay_finite(galaxy in galaxy_strategy()) {
+            let config = galaxy.config();
+            let mut controller = GalaxyController::from_config(&config);
+            // at least one pair of planets at distance 0
+            let position = controller.galaxy.planets[0].position;
+            controller.galaxy.planets.push(Planet::default(position[0], position[1], 1., config.planets.len()));
+            let planets = controller.galaxy.planets.clone();
+            for planet in planets.iter() {
+                let force = GalaxyController::compute_gravitational_force(planet, &planets, config.gravity, config.softening_kernel, config.softening_factor);
+                prop_assert!(force.iter().all(|f| f.is_finite()), "planet {}: {:?}", planet.id, force);
+            }
+            controller.update(config.frame_time_step);
+            prop_assert_eq!(controller.check_health(), Ok(()));
+            prop_assert!(controller.error.is_none());
+            prop_assert!(controller.total_energy().is_finite());
+        }
+    }
 }
 
 /// optimized disc intersection check
@@ -61,16 +614,78 @@ fn intersect(planet: &Planet, other_planet: &Planet) -> bool {
     square_len(sub(other_planet.position, planet.position)) <= (other_planet.r + planet.r) * (other_planet.r + planet.r)
 }
 
+/// f applied to every planet (index, planet), across cores with the `parallel` feature
+/// results are in planet order either way: each one only depends on its planet, so both paths give identical results
+fn map_planets<T, F>(planets: &[Planet], f: F) -> Vec<T>
+    where T: Send, F: Fn((usize, &Planet)) -> T + Sync + Send {
+    #[cfg(feature = "parallel")]
+    let results = planets.par_iter().enumerate().map(f).collect();
+    #[cfg(not(feature = "parallel"))]
+    let results = planets.iter().enumerate().map(f).collect();
+    results
+}
+
+/// bounding box [min x, max x, min y, max y] of a planet moving for dt
+fn swept_box(planet: &Planet, dt: Scalar) -> [Scalar; 4] {
+    let end = add(planet.position, mul_scalar(planet.velocity, dt));
+    [
+        planet.position[0].min(end[0]) - planet.r,
+        planet.position[0].max(end[0]) + planet.r,
+        planet.position[1].min(end[1]) - planet.r,
+        planet.position[1].max(end[1]) + planet.r,
+    ]
+}
+
+fn overlap(a: &[Scalar; 4], b: &[Scalar; 4]) -> bool {
+    a[0] <= b[1] && b[0] <= a[1] && a[2] <= b[3] && b[2] <= a[3]
+}
+
+/// time of impact of two separated discs moving at constant velocity (swept circles)
+/// None if they are already intersecting, moving apart or never touch
+fn time_of_impact(planet: &Planet, other_planet: &Planet) -> Option<Scalar> {
+    let delta = sub(other_planet.position, planet.position);
+    let dv = sub(other_planet.velocity, planet.velocity);
+    let r = planet.r + other_planet.r;
+    // |delta + dv.t| = r  <=>  a.t^2 + b.t + c = 0
+    let (a, b, c) = (square_len(dv), 2. * dot(delta, dv), square_len(delta) - r * r);
+    if c <= 0. || b >= 0. {
+        return None;
+    }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    Some((-b - discriminant.sqrt()) / (2. * a))
+}
+
 
 impl GalaxyController {
     /// Creates a new galaxy controller.
     pub fn new(galaxy: Galaxy, camera: Camera, settings: GalaxySettings) -> GalaxyController {
-        GalaxyController {
+        let initial_planets = galaxy.planets.clone();
+        let mut controller = GalaxyController {
             galaxy,
             camera,
             settings,
             cursor: [0.; 2],
-        }
+            accelerations: vec![],
+            arrays: PlanetArrays::new(),
+            tree: QuadTree::new(),
+            contacts: vec![],
+            trails: vec![],
+            simulated_time: 0.,
+            last_dt: 0.,
+            substeps: 0,
+            previous_positions: vec![],
+            render_alpha: 1.,
+            reference_energy: 0.,
+            initial_planets,
+            paused: false,
+            error: None,
+            dropped_impacts: 0,
+        };
+        controller.reference_energy = controller.total_energy();
+        controller
     }
 
     /// Creates a new galaxy controller from config.
@@ -78,21 +693,35 @@ impl GalaxyController {
         GalaxyController::new(Galaxy::from_config(config), Camera::from_config(config), GalaxySettings::from_config(config))
     }
 
-    fn compute_gravitational_force(planet: &Planet, other_planets: &[Planet], gravity: Scalar, softening_factor: Scalar) -> Vec2d<Scalar> {
+    /// reference (array of structs) implementation of the gravity kernel, see `PlanetArrays::acceleration`
+    /// (tests and benchmarks only)
+    #[doc(hidden)]
+    pub fn compute_gravitational_force(planet: &Planet, other_planets: &[Planet], gravity: Scalar, softening_kernel: SofteningKernel, softening_factor: Scalar) -> Vec2d<Scalar> {
         other_planets.iter().fold([0.0, 0.0], |acc, p| {
-            let acceleration = GalaxyController::compute_single_gravitational_force(planet, p, gravity, softening_factor);
+            let acceleration = GalaxyController::compute_single_gravitational_force(planet, p, gravity, softening_kernel, softening_factor);
             add(acc, acceleration)
         },
         )
     }
 
-    fn compute_single_gravitational_force(planet: &Planet, other_planet: &Planet, gravity: Scalar, softening_factor: Scalar) -> Vec2d<Scalar> {
-        if planet.id == other_planet.id {
+    fn compute_single_gravitational_force(planet: &Planet, other_planet: &Planet, gravity: Scalar, softening_kernel: SofteningKernel, softening_factor: Scalar) -> Vec2d<Scalar> {
+        let sq_distance = square_len(sub(other_planet.position, planet.position));
+        // coincident planets: no direction, no force (same as the gravity kernel)
+        if planet.id == other_planet.id || sq_distance == 0. {
             return [0., 0.];
         }
-        let sq_distance = square_len(sub(other_planet.position, planet.position));
-        let acc = gravity * other_planet.mass / (sq_distance + softening_factor);
-        mul_scalar(sub(other_planet.position, planet.position), acc / sq_distance.sqrt())
+        let acc = gravity * other_planet.mass * softening_kernel.force_factor(sq_distance, softening_factor);
+        mul_scalar(sub(other_planet.position, planet.position), acc)
+    }
+
+    // potential (per unit mass) of the softened force above (see `SofteningKernel::potential`)
+    fn compute_single_potential(position: Vec2d<Scalar>, other_planet: &Planet, gravity: Scalar, softening_kernel: SofteningKernel, softening_factor: Scalar) -> Scalar {
+        let distance = square_len(sub(other_planet.position, position)).sqrt();
+        gravity * other_planet.mass * softening_kernel.potential(distance, softening_factor)
+    }
+
+    fn compute_single_potential_energy(planet: &Planet, other_planet: &Planet, gravity: Scalar, softening_kernel: SofteningKernel, softening_factor: Scalar) -> Scalar {
+        planet.mass * GalaxyController::compute_single_potential(planet.position, other_planet, gravity, softening_kernel, softening_factor)
     }
 
     fn compute_impulse(planet: &Planet, other_planets: &[Planet], restitution_factor: Scalar, softening_factor: Scalar) -> Vec2d<Scalar> {
@@ -114,7 +743,8 @@ impl GalaxyController {
 
         // if velocity along normal > 0 then
         // planets are already separating
-        if planet.id == other_planet.id || !intersect || velocity_along_normal > 0. {
+        // coincident planets: no collision normal, they separate on their own velocities
+        if planet.id == other_planet.id || distance == 0. || !intersect || velocity_along_normal > 0. {
             [0., 0.]
         } else {
             let j = -(1. + restitution_factor) * velocity_along_normal;
@@ -123,43 +753,334 @@ impl GalaxyController {
         }
     }
 
-    fn compute_gravitational_interactions(&mut self, dt: f64) {
-        for i in 0..self.galaxy.planets.len() {
-            let mut acceleration: Vec2d<Scalar> = [0., 0.];
-            if let Some(planet) = self.galaxy.planets.get(i) {
-                acceleration = GalaxyController::compute_gravitational_force(&planet, &self.galaxy.planets, self.settings.gravity, self.settings.softening_factor);
-            }
-            if let Some(planet) = self.galaxy.planets.get_mut(i) {
-                planet.velocity = add(planet.velocity, mul_scalar(acceleration, dt));
+    /// Computes the net gravitational acceleration of each planet (see `accelerations`).
+    pub fn compute_accelerations(&mut self) {
+        let planets = &self.galaxy.planets;
+        let (gravity, softening_kernel, softening_factor) = (self.settings.gravity, self.settings.softening_kernel, self.settings.softening_factor);
+        self.arrays.update(planets);
+        let arrays = &self.arrays;
+        self.accelerations = match self.settings.gravity_solver {
+            GravitySolver::Direct => map_planets(planets, |(_, planet)| arrays.acceleration(planet.position, gravity, softening_kernel, softening_factor)),
+            GravitySolver::BarnesHut => {
+                self.tree.update(arrays);
+                let (tree, theta) = (&self.tree, self.settings.barnes_hut_theta);
+                map_planets(planets, |(_, planet)| tree.acceleration(planet.position, theta, gravity, softening_kernel, softening_factor))
             }
+        };
+    }
+
+    /// Applies accelerations (see `compute_accelerations`) to planet velocities for dt.
+    pub fn compute_gravitational_interactions(&mut self, dt: f64) {
+        for (planet, acceleration) in self.galaxy.planets.iter_mut().zip(self.accelerations.iter()) {
+            planet.velocity = add(planet.velocity, mul_scalar(*acceleration, dt));
         }
     }
 
-    fn compute_impulses(&mut self) {
-        for i in 0..self.galaxy.planets.len() {
-            let mut impulse: Vec2d<Scalar> = [0., 0.];
-            if let Some(planet) = self.galaxy.planets.get(i) {
-                impulse = GalaxyController::compute_impulse(&planet, &self.galaxy.planets, self.settings.restitution_factor, self.settings.softening_factor);
+    /// Adaptive step (current accelerations): time_step_accuracy * min over planets of
+    /// sqrt(softening length / |a|) (close encounters) and r / |v| (fast planets),
+    /// clamped to [min_time_step, max_dt].
+    fn adaptive_dt(&self, max_dt: Scalar) -> Scalar {
+        let softening_length = self.settings.softening_factor.sqrt();
+        let mut time_scale = Scalar::INFINITY;
+        for (planet, acceleration) in self.galaxy.planets.iter().zip(self.accelerations.iter()) {
+            let acceleration = square_len(*acceleration).sqrt();
+            if acceleration > 0. {
+                time_scale = time_scale.min((softening_length / acceleration).sqrt());
             }
-            if let Some(planet) = self.galaxy.planets.get_mut(i) {
-                planet.impulse = impulse;
+            let speed = square_len(planet.velocity).sqrt();
+            if speed > 0. {
+                time_scale = time_scale.min(planet.r / speed);
             }
         }
+        (self.settings.time_step_accuracy * time_scale).max(self.settings.min_time_step).min(max_dt)
+    }
+
+    /// Computes collision impulses of intersecting planets, applied by the next position update.
+    /// Resolved collisions are appended to `contacts`: `update` clears them once per update so that
+    /// contacts of every substep are kept, other callers clear them as needed.
+    pub fn compute_impulses(&mut self) {
+        let planets = &self.galaxy.planets;
+        let (restitution_factor, softening_factor) = (self.settings.restitution_factor, self.settings.softening_factor);
+        let impulses: Vec<(Vec2d<Scalar>, Vec<Contact>)> = map_planets(planets, |(i, planet)| {
+            let impulse = GalaxyController::compute_impulse(planet, planets, restitution_factor, softening_factor);
+            // record each colliding pair once
+            let contacts = planets[i + 1..].iter().filter_map(|other_planet| {
+                let pair_impulse = GalaxyController::compute_single_impulse(planet, other_planet, restitution_factor, softening_factor);
+                if pair_impulse != [0., 0.] {
+                    Some(Contact::new(planet, other_planet, pair_impulse))
+                } else {
+                    None
+                }
+            }).collect();
+            (impulse, contacts)
+        });
+        for (planet, (impulse, contacts)) in self.galaxy.planets.iter_mut().zip(impulses) {
+            planet.impulse = impulse;
+            if impulse != [0., 0.] {
+                planet.collisions += 1;
+            }
+            self.contacts.extend(contacts);
+        }
     }
 
     fn compute_positions(&mut self, dt: f64) {
         for planet in self.galaxy.planets.iter_mut() {
             planet.velocity = add(planet.velocity, planet.impulse);
+        }
+        // planets move until the next impact, which is resolved, then move on
+        // impact times are counted from the start of the step: other pairs keep their velocities, so their times still hold
+        let mut impacts = self.broadphase_impacts(dt);
+        let mut elapsed = 0.;
+        let max_impacts = MAX_IMPACTS_PER_STEP.max(MAX_IMPACTS_PER_PLANET * self.galaxy.planets.len());
+        for _ in 0..max_impacts {
+            let next = impacts.iter().cloned().min_by(|(i, j, t), (other_i, other_j, other_t)| {
+                t.total_cmp(other_t).then((i, j).cmp(&(other_i, other_j)))
+            });
+            match next {
+                Some((i, j, t)) => {
+                    self.move_planets(t - elapsed);
+                    elapsed = t;
+                    self.resolve_impact(i, j);
+                    // i and j bounced: their pairs are checked again over the rest of the step
+                    impacts.retain(|(other_i, other_j, _)| ![i, j].contains(other_i) && ![i, j].contains(other_j));
+                    let mut recheck = self.impacts_with(i, dt - elapsed);
+                    recheck.extend(self.impacts_with(j, dt - elapsed).into_iter().filter(|(k, l, _)| (*k, *l) != (i, j)));
+                    impacts.extend(recheck.into_iter().map(|(k, l, t)| (k, l, elapsed + t)));
+                }
+                None => break,
+            }
+        }
+        // cap hit: the remaining impacts of the step are skipped, planets may overlap until the next step
+        self.dropped_impacts += impacts.len();
+        self.move_planets(dt - elapsed);
+    }
+
+    fn move_planets(&mut self, dt: f64) {
+        for planet in self.galaxy.planets.iter_mut() {
             planet.position = add(planet.position, mul_scalar(planet.velocity, dt));
         }
     }
 
+    /// impacts (planet indices i < j, time) within dt, broadphase: only pairs whose swept boxes overlap
+    /// are tested (sort and sweep along x)
+    fn broadphase_impacts(&self, dt: f64) -> Vec<(usize, usize, Scalar)> {
+        let planets = &self.galaxy.planets;
+        let boxes: Vec<[Scalar; 4]> = planets.iter().map(|p| swept_box(p, dt)).collect();
+        let mut order: Vec<usize> = (0..planets.len()).collect();
+        order.sort_by(|a, b| boxes[*a][0].total_cmp(&boxes[*b][0]));
+        let mut impacts = vec![];
+        for (k, &a) in order.iter().enumerate() {
+            for &b in order[k + 1..].iter() {
+                // sorted by min x: no other box overlaps along x
+                if boxes[b][0] > boxes[a][1] {
+                    break;
+                }
+                if overlap(&boxes[a], &boxes[b]) {
+                    let (i, j) = (a.min(b), a.max(b));
+                    if let Some(t) = time_of_impact(&planets[i], &planets[j]).filter(|t| *t <= dt) {
+                        impacts.push((i, j, t));
+                    }
+                }
+            }
+        }
+        impacts
+    }
+
+    /// impacts (planet indices i < j, time) of planet k within dt
+    fn impacts_with(&self, k: usize, dt: f64) -> Vec<(usize, usize, Scalar)> {
+        let planets = &self.galaxy.planets;
+        let planet_box = swept_box(&planets[k], dt);
+        planets.iter().enumerate().filter(|(other, p)| *other != k && overlap(&planet_box, &swept_box(p, dt))).filter_map(|(other, _)| {
+            let (i, j) = (k.min(other), k.max(other));
+            time_of_impact(&planets[i], &planets[j]).filter(|t| *t <= dt).map(|t| (i, j, t))
+        }).collect()
+    }
+
+    /// collision impulse between touching planets i < j
+    fn resolve_impact(&mut self, i: usize, j: usize) {
+        let (left, right) = self.galaxy.planets.split_at_mut(j);
+        let (planet, other_planet) = (&mut left[i], &mut right[0]);
+        let delta = sub(other_planet.position, planet.position);
+        let distance = square_len(delta).sqrt();
+        // touching planets are r apart, unless both radii are tiny: no collision normal
+        if distance == 0. {
+            return;
+        }
+        let normal = mul_scalar(delta, 1. / distance);
+        let velocity_along_normal = dot(normal, sub(other_planet.velocity, planet.velocity));
+        let j = -(1. + self.settings.restitution_factor) * velocity_along_normal / (planet.imass + other_planet.imass);
+        let impulse = mul_scalar(normal, -j * planet.imass);
+        planet.velocity = add(planet.velocity, impulse);
+        other_planet.velocity = add(other_planet.velocity, mul_scalar(normal, j * other_planet.imass));
+        planet.collisions += 1;
+        other_planet.collisions += 1;
+        self.contacts.push(Contact::new(planet, other_planet, impulse));
+    }
+
+    fn record_trails(&mut self) {
+        let trail_length = self.settings.trail_length;
+        if trail_length == 0 {
+            self.trails.clear();
+            return;
+        }
+        // planets added since the last update start a new trail
+        self.trails.resize_with(self.galaxy.planets.len(), VecDeque::new);
+        for (trail, planet) in self.trails.iter_mut().zip(self.galaxy.planets.iter()) {
+            trail.push_back(planet.position);
+            while trail.len() > trail_length {
+                trail.pop_front();
+            }
+        }
+    }
+
+
+    /// Applies a (reloaded) config: physics settings and camera parameters, planets are kept.
+    /// Camera position and zoom are only applied if they changed since `previous` config
+    /// so that reloading does not undo camera moves.
+    pub fn apply_config(&mut self, config: &Config, previous: &Config) {
+        self.settings = GalaxySettings::from_config(config);
+        self.camera.zoom_factor = config.zoom_factor;
+        self.camera.camera_speed = config.camera_speed;
+        if config.camera_position != previous.camera_position {
+            self.camera.position = config.camera_position;
+        }
+        if config.default_zoom != previous.default_zoom {
+            self.camera.zoom = config.default_zoom;
+        }
+        self.initial_planets = Galaxy::from_config(config).planets;
+        // the potential energy depends on gravity and softening
+        self.reference_energy = self.total_energy();
+    }
+
+    /// Restores the initial planets.
+    pub fn reset(&mut self) {
+        self.galaxy.planets = self.initial_planets.clone();
+        self.accelerations.clear();
+        self.contacts.clear();
+        self.trails.clear();
+        self.previous_positions.clear();
+        self.simulated_time = 0.;
+        self.error = None;
+        self.dropped_impacts = 0;
+        self.reference_energy = self.total_energy();
+    }
 
     /// Update planet positions according to gravitational interactions.
+    /// With adaptive time step, dt is subdivided in shorter steps during close encounters
+    /// (see `adaptive_dt`), the simulation still advances by dt.
+    /// Each step is health checked: on failure, planets are restored to the last finite state,
+    /// `error` is set and the simulation is paused.
     pub fn update(&mut self, dt: f64) {
-        self.compute_gravitational_interactions(dt);
-        self.compute_impulses();
-        self.compute_positions(dt);
+        self.previous_positions = self.galaxy.planets.iter().map(|p| p.position).collect();
+        self.contacts.clear();
+        self.substeps = 0;
+        self.error = None;
+        let mut remaining = dt;
+        while remaining > 0. {
+            let planets = self.galaxy.planets.clone();
+            let contacts = self.contacts.len();
+            self.compute_accelerations();
+            let mut step = if self.settings.adaptive_time_step { self.adaptive_dt(remaining) } else { remaining };
+            // no sliver step left because of rounding
+            if remaining - step < remaining * 1e-9 {
+                step = remaining;
+            }
+            self.compute_gravitational_interactions(step);
+            self.compute_impulses();
+            self.compute_positions(step);
+            if let Err(error) = self.check_health() {
+                // accelerations and contacts match the restored planets (coloring, overlays)
+                self.galaxy.planets = planets;
+                self.contacts.truncate(contacts);
+                self.compute_accelerations();
+                self.error = Some(format!("time {:.3}: {}", self.simulated_time + dt - remaining, error));
+                self.paused = true;
+                break;
+            }
+            remaining -= step;
+            self.substeps += 1;
+        }
+        self.record_trails();
+        self.simulated_time += dt - remaining;
+        self.last_dt = dt;
+    }
+
+    /// Checks that planet positions, velocities and accelerations are finite.
+    /// Err lists the offending planets (ids). Checking each step points at the planets that caused it
+    /// for non-finite velocities only: a non-finite position makes every acceleration non-finite within the step.
+    pub fn check_health(&self) -> Result<(), String> {
+        let finite = |v: Vec2d<Scalar>| v[0].is_finite() && v[1].is_finite();
+        let mut violations: Vec<String> = vec![];
+        for (i, planet) in self.galaxy.planets.iter().enumerate() {
+            let mut fields = vec![];
+            if !finite(planet.position) {
+                fields.push("position");
+            }
+            if !finite(planet.velocity) {
+                fields.push("velocity");
+            }
+            if self.accelerations.get(i).map(|a| !finite(*a)).unwrap_or(false) {
+                fields.push("acceleration");
+            }
+            if !fields.is_empty() {
+                violations.push(format!("planet {}: non-finite {}", planet.id, fields.join(", ")));
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations.join("; "))
+        }
+    }
+
+    /// Position planet i is drawn at (see `render_alpha`).
+    pub fn render_position(&self, i: usize) -> Vec2d<Scalar> {
+        let position = self.galaxy.planets[i].position;
+        match self.previous_positions.get(i) {
+            // planets added since the last update are not interpolated
+            Some(previous) => add(*previous, mul_scalar(sub(position, *previous), self.render_alpha)),
+            None => position,
+        }
+    }
+
+    /// Total kinetic energy of the galaxy.
+    pub fn kinetic_energy(&self) -> Scalar {
+        self.galaxy.planets.iter().map(|p| 0.5 * p.mass * square_len(p.velocity)).sum()
+    }
+
+    /// Total gravitational potential energy of the galaxy (each pair counted once).
+    pub fn potential_energy(&self) -> Scalar {
+        let planets = &self.galaxy.planets;
+        let mut energy = 0.;
+        for (i, planet) in planets.iter().enumerate() {
+            for other_planet in planets[i + 1..].iter() {
+                energy += GalaxyController::compute_single_potential_energy(planet, other_planet, self.settings.gravity, self.settings.softening_kernel, self.settings.softening_factor);
+            }
+        }
+        energy
+    }
+
+    /// Gravitational potential (per unit mass) at world position.
+    pub fn potential_at(&self, position: Vec2d<Scalar>) -> Scalar {
+        self.galaxy.planets.iter().map(|p| {
+            GalaxyController::compute_single_potential(position, p, self.settings.gravity, self.settings.softening_kernel, self.settings.softening_factor)
+        }).sum()
+    }
+
+    /// Total (kinetic + potential) energy of the galaxy.
+    pub fn total_energy(&self) -> Scalar {
+        self.kinetic_energy() + self.potential_energy()
+    }
+
+    /// Energy drift relative to the reference energy
+    /// e.g. 0.01 => total energy is 1% higher than the reference energy
+    pub fn energy_drift(&self) -> Scalar {
+        let drift = self.total_energy() - self.reference_energy;
+        if self.reference_energy != 0. {
+            drift / self.reference_energy.abs()
+        } else {
+            drift
+        }
     }
 
     /// Handles events.
@@ -174,11 +1095,14 @@ impl GalaxyController {
             Some(Button::Keyboard(Key::Down)) => self.camera.position = add(self.camera.position, [0., self.camera.camera_speed]),
             Some(Button::Keyboard(Key::PageDown)) => self.camera.zoom /= self.camera.zoom_factor,
             Some(Button::Keyboard(Key::PageUp)) => self.camera.zoom *= self.camera.zoom_factor,
+            Some(Button::Keyboard(Key::Backspace)) => self.reset(),
+            Some(Button::Keyboard(Key::Space)) => self.paused = !self.paused,
             Some(Button::Mouse(MouseButton::Left)) => {
                 let v = self.camera.view_to_world_position(self.cursor);
                 self.galaxy.planets.push(
                     Planet::default(v[0], v[1], self.settings.planet_radius, self.galaxy.planets.len())
                 );
+                self.reference_energy = self.total_energy();
             },
             _ => ()
         }
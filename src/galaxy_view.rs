@@ -1,14 +1,43 @@
 //! Galaxy view (does all the drawing)
 
-use graphics::{Context, Image};
+use graphics::{Context, Image, Line, Rectangle, Text, Transformed};
+use graphics::character::CharacterCache;
 use graphics::Ellipse;
 use graphics::ellipse::circle;
-use graphics::types::Color;
-use opengl_graphics::{GlGraphics, Texture};
+use graphics::math::{add, mul_scalar, Scalar, Vec2d};
+use graphics::types::{Color, FontSize};
+use opengl_graphics::{GlGraphics, GlyphCache, Texture};
+use piston::input::{Button, GenericEvent, Key};
 use piston_window::TextureSettings;
 
+use crate::camera::Camera;
+use crate::colormap::{ColorScale, PlanetColoring};
 use crate::config::Config;
 pub use crate::galaxy_controller::GalaxyController;
+use crate::hud::Hud;
+use crate::potential_field::PotentialField;
+use crate::world_grid::WorldGrid;
+
+/// velocity arrows color (window and SVG export)
+pub const VELOCITY_COLOR: Color = [0.2, 1.0, 0.2, 1.0];
+/// gravitational acceleration arrows color
+const ACCELERATION_COLOR: Color = [1.0, 0.2, 0.2, 1.0];
+/// collision normals color
+const CONTACT_NORMAL_COLOR: Color = [0.2, 0.8, 1.0, 1.0];
+/// collision impulse arrows color
+const IMPULSE_COLOR: Color = [1.0, 1.0, 0.2, 1.0];
+/// collision normals half length (pixels)
+const CONTACT_NORMAL_LENGTH: Scalar = 10.;
+/// arrow head size (pixels)
+const ARROW_HEAD_SIZE: Scalar = 4.;
+/// vector line radius (pixels)
+const VECTOR_RADIUS: Scalar = 0.5;
+/// legend bar size (pixels)
+const LEGEND_SIZE: [Scalar; 2] = [12., 160.];
+/// legend bar margin to the window border (pixels)
+const LEGEND_MARGIN: Scalar = 16.;
+/// legend bar color steps
+const LEGEND_STEPS: usize = 64;
 
 /// Galaxy view settings
 pub struct GalaxyViewSettings {
@@ -16,6 +45,14 @@ pub struct GalaxyViewSettings {
     planet_color: Color,
     /// planet texture
     planet_texture: Option<Texture>,
+    /// HUD text color
+    hud_color: Color,
+    /// HUD font size
+    hud_font_size: FontSize,
+    /// HUD font
+    glyphs: Option<GlyphCache<'static>>,
+    /// planet coloring by physical quantity
+    coloring: PlanetColoring,
 }
 
 impl GalaxyViewSettings {
@@ -34,9 +71,47 @@ impl GalaxyViewSettings {
                     Err(_) => None,
                 }
         };
+        let glyphs = match &config.hud_font_path {
+            None => None,
+            Some(path_string) => GlyphCache::new(path_string, (), TextureSettings::new()).ok(),
+        };
         GalaxyViewSettings {
             planet_color: config.planet_color,
             planet_texture: texture,
+            hud_color: config.hud_color,
+            hud_font_size: config.hud_font_size,
+            glyphs,
+            coloring: PlanetColoring::from_config(config),
+        }
+    }
+}
+
+/// Debug overlays: vectors drawn on top of planets
+pub struct Overlays {
+    /// draw velocity arrows
+    pub velocities: bool,
+    /// velocity arrow length = velocity * velocity_scale (world units)
+    pub velocity_scale: Scalar,
+    /// draw net gravitational acceleration arrows
+    pub accelerations: bool,
+    /// acceleration arrow length = acceleration * acceleration_scale (world units)
+    pub acceleration_scale: Scalar,
+    /// draw collision normals and impulses
+    pub contacts: bool,
+    /// impulse arrow length = impulse * impulse_scale (world units)
+    pub impulse_scale: Scalar,
+}
+
+impl Overlays {
+    /// Creates debug overlays from config
+    pub fn from_config(config: &Config) -> Overlays {
+        Overlays {
+            velocities: config.show_velocities,
+            velocity_scale: config.velocity_scale,
+            accelerations: config.show_accelerations,
+            acceleration_scale: config.acceleration_scale,
+            contacts: config.show_contacts,
+            impulse_scale: config.impulse_scale,
         }
     }
 }
@@ -44,29 +119,91 @@ impl GalaxyViewSettings {
 /// Stores visual information about a galaxy.
 pub struct GalaxyView {
     /// stores galaxy view settings
-    pub settings: GalaxyViewSettings
+    pub settings: GalaxyViewSettings,
+    /// stores HUD state
+    pub hud: Hud,
+    /// stores debug overlays state
+    pub overlays: Overlays,
+    /// stores gravitational potential field
+    pub potential: PotentialField,
+    /// stores world grid
+    pub grid: WorldGrid,
 }
 
 impl GalaxyView {
     /// creates a new Galaxy view.
-    pub fn new(settings: GalaxyViewSettings) -> GalaxyView {
+    pub fn new(settings: GalaxyViewSettings, hud: Hud, overlays: Overlays, potential: PotentialField, grid: WorldGrid) -> GalaxyView {
         GalaxyView {
             settings,
+            hud,
+            overlays,
+            potential,
+            grid,
         }
     }
 
     /// Creates a default galaxy view
     pub fn default() -> GalaxyView {
-        GalaxyView::new(GalaxyViewSettings::default())
+        GalaxyView::from_config(&Config::default())
     }
 
     /// Creates a galaxy view from config
     pub fn from_config(config: &Config) -> GalaxyView {
-        GalaxyView::new(GalaxyViewSettings::from_config(config))
+        GalaxyView::new(
+            GalaxyViewSettings::from_config(config),
+            Hud::from_config(config),
+            Overlays::from_config(config),
+            PotentialField::from_config(config),
+            WorldGrid::from_config(config),
+        )
+    }
+
+    /// Applies a (reloaded) config: colors, texture and fonts, display toggles and coloring (M, N, L keys) are kept.
+    pub fn apply_config(&mut self, config: &Config) {
+        let settings = GalaxyViewSettings::from_config(config);
+        let coloring = std::mem::replace(&mut self.settings, settings).coloring;
+        self.settings.coloring = coloring;
+        self.potential.invalidate();
+    }
+
+    /// Handles events.
+    pub fn event<E: GenericEvent>(&mut self, e: &E) {
+        match e.press_args() {
+            Some(Button::Keyboard(Key::H)) => self.hud.visible = !self.hud.visible,
+            Some(Button::Keyboard(Key::V)) => self.overlays.velocities = !self.overlays.velocities,
+            Some(Button::Keyboard(Key::F)) => self.overlays.accelerations = !self.overlays.accelerations,
+            Some(Button::Keyboard(Key::C)) => self.overlays.contacts = !self.overlays.contacts,
+            Some(Button::Keyboard(Key::M)) => self.settings.coloring.mode = self.settings.coloring.mode.next(),
+            Some(Button::Keyboard(Key::N)) => {
+                self.settings.coloring.colormap = self.settings.coloring.colormap.next();
+                self.potential.invalidate();
+            },
+            Some(Button::Keyboard(Key::L)) => self.settings.coloring.scale = match self.settings.coloring.scale {
+                ColorScale::Linear => ColorScale::Log,
+                ColorScale::Log => ColorScale::Linear,
+            },
+            Some(Button::Keyboard(Key::P)) => self.potential.visible = !self.potential.visible,
+            Some(Button::Keyboard(Key::I)) => self.potential.contours = !self.potential.contours,
+            Some(Button::Keyboard(Key::G)) => self.grid.visible = !self.grid.visible,
+            _ => ()
+        }
     }
 
     /// Draw galaxy
-    pub fn draw(&self, controller: &GalaxyController, c: &Context, g: &mut GlGraphics) {
+    pub fn draw(&mut self, controller: &GalaxyController, c: &Context, g: &mut GlGraphics) {
+        self.hud.fps.tick();
+        self.potential.draw(controller, self.settings.coloring.colormap, c, g);
+        self.grid.draw(&controller.camera, c, g);
+        self.draw_planets(controller, c, g);
+        self.draw_overlays(controller, c, g);
+        self.draw_legend(controller, c, g);
+        self.grid.draw_scale_bar(&controller.camera, self.settings.glyphs.as_mut(), self.settings.hud_font_size, c, g);
+        if self.hud.visible {
+            self.draw_hud(controller, c, g);
+        }
+    }
+
+    fn draw_planets(&self, controller: &GalaxyController, c: &Context, g: &mut GlGraphics) {
         let settings = &self.settings;
 
         // Nb: IDE borrow checker complains about 'c' but code compiles just fine.
@@ -77,12 +214,110 @@ impl GalaxyView {
 
         let transform = controller.camera.world_to_view_transform(c.transform);
 
-        for planet in planets.iter() {
+        if let Some(colors) = settings.coloring.colors(controller) {
+            for (i, (planet, color)) in planets.iter().zip(colors.iter()).enumerate() {
+                let position = controller.render_position(i);
+                Ellipse::new(*color).draw(circle(position[0], position[1], planet.r), &c.draw_state, transform, g);
+            }
+            return;
+        }
+
+        for (i, planet) in planets.iter().enumerate() {
+            let position = controller.render_position(i);
             if let Some(texture) = &settings.planet_texture {
-                image.rect(circle(planet.position[0], planet.position[1], planet.r)).draw(texture, &c.draw_state, transform, g);
+                image.rect(circle(position[0], position[1], planet.r)).draw(texture, &c.draw_state, transform, g);
             } else {
-                disc.draw(circle(planet.position[0], planet.position[1], planet.r), &c.draw_state, transform, g);
+                disc.draw(circle(position[0], position[1], planet.r), &c.draw_state, transform, g);
             }
         }
     }
-}
\ No newline at end of file
+
+    fn draw_legend(&mut self, controller: &GalaxyController, c: &Context, g: &mut GlGraphics) {
+        let settings = &mut self.settings;
+        let coloring = &settings.coloring;
+        let (min, max) = match coloring.range(controller) {
+            Some(range) => range,
+            None => return,
+        };
+        let view_size = c.get_view_size();
+        let x = view_size[0] - LEGEND_MARGIN - LEGEND_SIZE[0];
+        let y = LEGEND_MARGIN + Scalar::from(settings.hud_font_size) * 2.;
+        let step_height = LEGEND_SIZE[1] / LEGEND_STEPS as Scalar;
+        for i in 0..LEGEND_STEPS {
+            // max at the top
+            let t = 1. - i as Scalar / (LEGEND_STEPS - 1) as Scalar;
+            let color = coloring.colormap.color(t);
+            Rectangle::new(color).draw([x, y + i as Scalar * step_height, LEGEND_SIZE[0], step_height], &c.draw_state, c.transform, g);
+        }
+        if let Some(glyphs) = &mut settings.glyphs {
+            let text = Text::new_color(settings.hud_color, settings.hud_font_size);
+            let font_size = Scalar::from(settings.hud_font_size);
+            let labels = [
+                (coloring.mode.name().to_string(), y - font_size),
+                (format_value(max), y + font_size / 2.),
+                (format_value(min), y + LEGEND_SIZE[1]),
+            ];
+            for (label, label_y) in labels.iter() {
+                // right aligned with the legend bar
+                let width = glyphs.width(settings.hud_font_size, label).unwrap_or(0.);
+                let transform = c.transform.trans(x + LEGEND_SIZE[0] - width, *label_y);
+                text.draw(label, glyphs, &c.draw_state, transform, g).ok();
+            }
+        }
+    }
+
+    fn draw_overlays(&self, controller: &GalaxyController, c: &Context, g: &mut GlGraphics) {
+        let overlays = &self.overlays;
+        let camera = &controller.camera;
+        let planets = &controller.galaxy.planets;
+        if overlays.velocities {
+            for (i, planet) in planets.iter().enumerate() {
+                draw_vector(camera, controller.render_position(i), mul_scalar(planet.velocity, overlays.velocity_scale), VELOCITY_COLOR, c, g);
+            }
+        }
+        if overlays.accelerations {
+            // accelerations are missing for planets added since the last update
+            for (i, acceleration) in controller.accelerations.iter().enumerate().take(planets.len()) {
+                draw_vector(camera, controller.render_position(i), mul_scalar(*acceleration, overlays.acceleration_scale), ACCELERATION_COLOR, c, g);
+            }
+        }
+        if overlays.contacts {
+            let line = Line::new(CONTACT_NORMAL_COLOR, VECTOR_RADIUS);
+            for contact in controller.contacts.iter() {
+                let position = camera.world_to_view_position(contact.position);
+                let normal = mul_scalar(contact.normal, CONTACT_NORMAL_LENGTH);
+                line.draw([position[0] - normal[0], position[1] - normal[1], position[0] + normal[0], position[1] + normal[1]], &c.draw_state, c.transform, g);
+                draw_vector(camera, contact.position, mul_scalar(contact.impulse, overlays.impulse_scale), IMPULSE_COLOR, c, g);
+            }
+        }
+    }
+
+    fn draw_hud(&mut self, controller: &GalaxyController, c: &Context, g: &mut GlGraphics) {
+        let lines = self.hud.lines(controller);
+        let settings = &mut self.settings;
+        if let Some(glyphs) = &mut settings.glyphs {
+            let text = Text::new_color(settings.hud_color, settings.hud_font_size);
+            let line_height = Scalar::from(settings.hud_font_size) * 1.5;
+            for (i, line) in lines.iter().enumerate() {
+                let transform = c.transform.trans(8., line_height * (i + 1) as Scalar);
+                text.draw(line, glyphs, &c.draw_state, transform, g).ok();
+            }
+        }
+    }
+}
+
+/// Draw a world vector as an arrow of constant (screen) thickness.
+fn draw_vector(camera: &Camera, origin: Vec2d<Scalar>, vector: Vec2d<Scalar>, color: Color, c: &Context, g: &mut GlGraphics) {
+    let from = camera.world_to_view_position(origin);
+    let to = camera.world_to_view_position(add(origin, vector));
+    Line::new(color, VECTOR_RADIUS).draw_arrow([from[0], from[1], to[0], to[1]], ARROW_HEAD_SIZE, &c.draw_state, c.transform, g);
+}
+
+/// Format a legend value e.g. 12.35 or 1.23e-5
+fn format_value(value: Scalar) -> String {
+    if value == 0. || (value.abs() >= 0.01 && value.abs() < 10000.) {
+        format!("{:.2}", value)
+    } else {
+        format!("{:.2e}", value)
+    }
+}
@@ -0,0 +1,100 @@
+//! Gravity kernel: planets as a structure of arrays (SoA)
+//! the force loop only reads positions and masses, stored contiguously so that it vectorises
+
+use graphics::math::{Scalar, Vec2d};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::galaxy::Planet;
+use crate::softening::SofteningKernel;
+
+/// planets processed per block: pair terms are written to stack buffers, then summed
+const BLOCK: usize = 256;
+/// independent partial sums
+const LANES: usize = 8;
+
+/// how accelerations are computed
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GravitySolver {
+    /// every pair of planets (`PlanetArrays::acceleration`): exact, O(n^2)
+    Direct,
+    /// quadtree approximation (`QuadTree::acceleration`): O(n log n), for thousands of planets
+    BarnesHut,
+}
+
+/// Positions and masses of the planets, mirrored from `Galaxy::planets` before each force computation
+#[derive(Default)]
+pub struct PlanetArrays {
+    /// x positions
+    pub x: Vec<Scalar>,
+    /// y positions
+    pub y: Vec<Scalar>,
+    /// masses
+    pub mass: Vec<Scalar>,
+}
+
+impl PlanetArrays {
+    /// Creates empty planet arrays.
+    pub fn new() -> PlanetArrays {
+        PlanetArrays {
+            x: vec![],
+            y: vec![],
+            mass: vec![],
+        }
+    }
+
+    /// Copies planet positions and masses, allocations are reused.
+    pub fn update(&mut self, planets: &[Planet]) {
+        self.x.clear();
+        self.y.clear();
+        self.mass.clear();
+        self.x.extend(planets.iter().map(|p| p.position[0]));
+        self.y.extend(planets.iter().map(|p| p.position[1]));
+        self.mass.extend(planets.iter().map(|p| p.mass));
+    }
+
+    /// Gravitational acceleration at position due to every planet (same force as
+    /// `GalaxyController::compute_single_gravitational_force`).
+    /// Planets at position (distance 0, e.g. the planet itself) do not contribute.
+    pub fn acceleration(&self, position: Vec2d<Scalar>, gravity: Scalar, softening_kernel: SofteningKernel, softening_factor: Scalar) -> Vec2d<Scalar> {
+        // one loop per kernel: the kernel is not matched for every pair
+        let acceleration = match softening_kernel {
+            SofteningKernel::None => self.sum(position, |sq_distance| SofteningKernel::None.force_factor(sq_distance, softening_factor)),
+            SofteningKernel::Plummer => self.sum(position, |sq_distance| SofteningKernel::Plummer.force_factor(sq_distance, softening_factor)),
+            SofteningKernel::CubicSpline => self.sum(position, |sq_distance| SofteningKernel::CubicSpline.force_factor(sq_distance, softening_factor)),
+        };
+        [gravity * acceleration[0], gravity * acceleration[1]]
+    }
+
+    /// sum over planets of mass * factor(squared distance) * (dx, dy)
+    #[inline(always)]
+    fn sum<F: Fn(Scalar) -> Scalar>(&self, position: Vec2d<Scalar>, factor: F) -> Vec2d<Scalar> {
+        let (px, py) = (position[0], position[1]);
+        let mut ax = [0.; LANES];
+        let mut ay = [0.; LANES];
+        let mut fx = [0.; BLOCK];
+        let mut fy = [0.; BLOCK];
+        let blocks = self.x.chunks(BLOCK).zip(self.y.chunks(BLOCK)).zip(self.mass.chunks(BLOCK));
+        for ((x, y), mass) in blocks {
+            // zipped slices: no bounds checks, this loop only stores so that it vectorises
+            let n = x.len();
+            let (block_fx, block_fy) = (&mut fx[..n], &mut fy[..n]);
+            let pairs = x.iter().zip(y.iter()).zip(mass.iter()).zip(block_fx.iter_mut().zip(block_fy.iter_mut()));
+            for (((x, y), mass), (fx, fy)) in pairs {
+                let (dx, dy) = (x - px, y - py);
+                let pair_factor = mass * factor(dx * dx + dy * dy);
+                *fx = dx * pair_factor;
+                *fy = dy * pair_factor;
+            }
+            // LANES partial sums rather than one dependency chain
+            for (lanes_fx, lanes_fy) in block_fx.chunks(LANES).zip(block_fy.chunks(LANES)) {
+                for (lane, (fx, fy)) in lanes_fx.iter().zip(lanes_fy.iter()).enumerate() {
+                    ax[lane] += fx;
+                    ay[lane] += fy;
+                }
+            }
+        }
+        [ax.iter().sum::<Scalar>(), ay.iter().sum::<Scalar>()]
+    }
+}
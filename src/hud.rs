@@ -0,0 +1,95 @@
+//! HUD (heads-up display): simulation statistics
+
+use std::time::Instant;
+
+use graphics::math::Scalar;
+
+use crate::config::Config;
+use crate::galaxy_controller::GalaxyController;
+
+/// Counts events per second
+pub struct RateCounter {
+    /// events since last refresh
+    count: u32,
+    /// last refresh
+    since: Instant,
+    /// events/second, refreshed every second
+    rate: Scalar,
+}
+
+impl Default for RateCounter {
+    fn default() -> RateCounter {
+        RateCounter::new()
+    }
+}
+
+impl RateCounter {
+    /// Creates a new rate counter.
+    pub fn new() -> RateCounter {
+        RateCounter {
+            count: 0,
+            since: Instant::now(),
+            rate: 0.,
+        }
+    }
+
+    /// Counts one event.
+    pub fn tick(&mut self) {
+        self.count += 1;
+        let elapsed = self.since.elapsed();
+        let elapsed = elapsed.as_secs() as Scalar + Scalar::from(elapsed.subsec_nanos()) * 1e-9;
+        if elapsed >= 1. {
+            self.rate = Scalar::from(self.count) / elapsed;
+            self.count = 0;
+            self.since = Instant::now();
+        }
+    }
+
+    /// events/second
+    pub fn rate(&self) -> Scalar {
+        self.rate
+    }
+}
+
+/// HUD state
+pub struct Hud {
+    /// HUD is displayed
+    pub visible: bool,
+    /// frames per second
+    pub fps: RateCounter,
+    /// physics steps per second (ticked by the caller for each `GalaxyController::update`)
+    pub ups: RateCounter,
+}
+
+impl Hud {
+    /// Creates a new HUD from config.
+    pub fn from_config(config: &Config) -> Hud {
+        Hud {
+            visible: config.hud_enabled,
+            fps: RateCounter::new(),
+            ups: RateCounter::new(),
+        }
+    }
+
+    /// Text lines displayed by the HUD.
+    pub fn lines(&self, controller: &GalaxyController) -> Vec<String> {
+        let cursor = controller.camera.view_to_world_position(controller.cursor);
+        let mut lines = vec![
+            format!("fps: {:.1}", self.fps.rate()),
+            format!("ups: {:.1}", self.ups.rate()),
+            format!("planets: {}", controller.galaxy.planets.len()),
+            format!("time: {:.2}", controller.simulated_time),
+            format!("dt: {:.3} ({} steps)", controller.last_dt, controller.substeps),
+            format!("zoom: {:.3}", controller.camera.zoom),
+            format!("cursor: ({:.1}, {:.1})", cursor[0], cursor[1]),
+            format!("energy: {:.4e} (drift {:+.3}%)", controller.total_energy(), controller.energy_drift() * 100.),
+        ];
+        if controller.dropped_impacts > 0 {
+            lines.push(format!("dropped impacts: {}", controller.dropped_impacts));
+        }
+        if let Some(error) = &controller.error {
+            lines.push(format!("error: {}", error));
+        }
+        lines
+    }
+}
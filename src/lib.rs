@@ -0,0 +1,40 @@
+//! Galaxy simulation: physics, configuration and rendering
+//! used by the viewer (main.rs) and the benchmarks
+
+extern crate gif;
+extern crate gl;
+extern crate graphics;
+extern crate image;
+extern crate opengl_graphics;
+extern crate piston;
+extern crate rand;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+extern crate ron;
+extern crate schemars;
+extern crate serde;
+extern crate serde_yaml;
+extern crate toml;
+
+pub use crate::camera::Camera;
+pub use crate::galaxy::Galaxy;
+pub use crate::galaxy_controller::GalaxyController;
+pub use crate::galaxy_view::{GalaxyView, GalaxyViewSettings};
+
+pub mod barnes_hut;
+pub mod galaxy;
+pub mod galaxy_controller;
+pub mod galaxy_view;
+pub mod gravity_kernel;
+pub mod camera;
+pub mod capture;
+pub mod colormap;
+pub mod config;
+pub mod config_watcher;
+pub mod hud;
+pub mod potential_field;
+pub mod simulation_clock;
+pub mod softening;
+pub mod software_renderer;
+pub mod svg_export;
+pub mod world_grid;
@@ -2,35 +2,203 @@
 
 //! Galaxy simulation
 
+extern crate galaxy;
 extern crate glutin_window;
 extern crate graphics;
 extern crate opengl_graphics;
 extern crate piston;
+extern crate structopt;
 
-use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use glutin_window::GlutinWindow;
 use graphics::clear;
+use graphics::math::Scalar;
 use opengl_graphics::{GlGraphics, OpenGL};
 use piston::event_loop::{EventLoop, Events, EventSettings};
 use piston::input::{RenderEvent, UpdateEvent};
 use piston::window::WindowSettings;
+use structopt::StructOpt;
 
-pub use crate::camera::Camera;
-use crate::config::Config;
-pub use crate::galaxy::Galaxy;
-pub use crate::galaxy_controller::GalaxyController;
-pub use crate::galaxy_view::{GalaxyView, GalaxyViewSettings};
+use galaxy::{GalaxyController, GalaxyView};
+use galaxy::capture::Capture;
+use galaxy::config::{self, Config};
+use galaxy::config_watcher::ConfigWatcher;
+use galaxy::simulation_clock::SimulationClock;
+use galaxy::software_renderer::{FrameWriter, SoftwareRenderer};
+use galaxy::svg_export::SvgExporter;
 
-mod galaxy;
-mod galaxy_controller;
-mod galaxy_view;
-mod camera;
-mod config;
+/// 2D gravity simulation
+#[derive(StructOpt)]
+#[structopt(name = "galaxy")]
+struct Args {
+    /// config file
+    #[structopt(long, parse(from_os_str), default_value = "res/config.json")]
+    config: PathBuf,
+    /// overrides a config field e.g. --set gravity=0.1 --set camera_position.x=-50
+    #[structopt(long = "set", number_of_values = 1)]
+    overrides: Vec<String>,
+    /// replaces config planets with a random scene (same seed, same scene)
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// starts with the simulation paused (space toggles pause)
+    #[structopt(long)]
+    paused: bool,
+    /// runs without window, rendering frames with the software renderer
+    #[structopt(long)]
+    headless: bool,
+    /// number of headless steps
+    #[structopt(long, default_value = "1000")]
+    steps: u64,
+    /// headless output: animated GIF if path ends with .gif,
+    /// last frame as SVG if path ends with .svg, PNG directory otherwise
+    #[structopt(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// captures the first frame (PNG) then exits
+    #[structopt(long, parse(from_os_str))]
+    screenshot: Option<PathBuf>,
+    /// exports the first frame (SVG) then exits
+    #[structopt(long, parse(from_os_str))]
+    svg: Option<PathBuf>,
+    /// starts recording right away
+    #[structopt(long)]
+    record: bool,
+    /// prints the default config (JSON) then exits
+    #[structopt(long)]
+    print_default_config: bool,
+    /// prints the config JSON schema then exits
+    #[structopt(long)]
+    dump_schema: bool,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+/// Subcommands
+#[derive(StructOpt)]
+enum Command {
+    /// rewrites config files in the latest config version, warns about deprecated keys
+    MigrateConfig {
+        /// config files (JSON, TOML, YAML or RON)
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+/// Migrates config files (see `Config::migrate_file`), exits with 1 if one could not be migrated.
+fn migrate_config(files: &[PathBuf]) {
+    let mut failed = false;
+    for path in files {
+        match Config::migrate_file(path) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    println!("warning: {}: {}", path.to_string_lossy(), warning);
+                }
+                println!("migrated {} to version {}", path.to_string_lossy(), config::CONFIG_VERSION);
+            }
+            Err(e) => {
+                println!("could not migrate {}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Runs the simulation without window, rendering every record_interval-th step with the software renderer.
+/// Err: output could not be written or the simulation failed its health check.
+fn run_headless(config: &Config, steps: u64, output: Option<PathBuf>) -> Result<(), String> {
+    let mut galaxy_controller = GalaxyController::from_config(config);
+    let renderer = SoftwareRenderer::from_config(config);
+    let svg_output = output.as_ref().filter(|path| path.extension().and_then(|e| e.to_str()) == Some("svg")).cloned();
+    let mut writer = match output {
+        None => None,
+        Some(_) if svg_output.is_some() => None,
+        Some(path) => match FrameWriter::create(&path, renderer.size) {
+            Ok(writer) => Some(writer),
+            Err(e) => return Err(format!("could not create {}: {}", path.to_string_lossy(), e)),
+        },
+    };
+    let interval = u64::from(config.record_interval.max(1));
+    let mut step = 0;
+    let started = Instant::now();
+    loop {
+        if let Some(writer) = &mut writer {
+            if let Err(e) = writer.write(&renderer.render(&galaxy_controller)) {
+                return Err(format!("could not write frame: {}", e));
+            }
+        }
+        if step >= steps {
+            break;
+        }
+        for _ in 0..interval.min(steps - step) {
+            galaxy_controller.update(config.frame_time_step);
+            if galaxy_controller.error.is_some() {
+                break;
+            }
+            step += 1;
+        }
+        if let Some(error) = &galaxy_controller.error {
+            return Err(format!("simulation stopped after {} steps, {}", step, error));
+        }
+    }
+    if let Some(path) = svg_output {
+        let view_size = [Scalar::from(renderer.size[0]), Scalar::from(renderer.size[1])];
+        if let Err(e) = SvgExporter::from_config(config).export(&galaxy_controller, view_size, &path) {
+            return Err(format!("could not save {}: {}", path.to_string_lossy(), e));
+        }
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "{} steps, simulated time: {:.2}, planets: {}, energy drift: {:+.3}%, wall time: {:.3}s",
+        step,
+        galaxy_controller.simulated_time,
+        galaxy_controller.galaxy.planets.len(),
+        galaxy_controller.energy_drift() * 100.,
+        elapsed.as_secs() as Scalar + Scalar::from(elapsed.subsec_nanos()) * 1e-9
+    );
+    if galaxy_controller.dropped_impacts > 0 {
+        println!("warning: {} impacts dropped (impact cap per step hit)", galaxy_controller.dropped_impacts);
+    }
+    Ok(())
+}
 
 fn main() {
     let opengl = OpenGL::V3_2;
-    let config = Config::from_path(Path::new("res/config.json"));
+    let args = Args::from_args();
+    if let Some(Command::MigrateConfig { files }) = &args.command {
+        migrate_config(files);
+        return;
+    }
+    if args.print_default_config {
+        println!("{:#}", Config::default().to_json());
+        return;
+    }
+    if args.dump_schema {
+        println!("{}", Config::schema());
+        return;
+    }
+    let mut overrides = args.overrides.clone();
+    if let Some(seed) = args.seed {
+        overrides.push(format!("seed={}", seed));
+    }
+    let mut config = Config::load(&args.config, &overrides);
+    if let Err(report) = config.validate() {
+        println!("invalid config {}:\n{}", args.config.to_string_lossy(), report);
+        std::process::exit(1);
+    }
+    if args.headless {
+        if let Err(e) = run_headless(&config, args.steps, args.output) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    for warning in config.window_warnings() {
+        println!("warning: {}", warning);
+    }
     let settings = WindowSettings::new("Galaxy", config.window_size)
         .opengl(opengl)
         .exit_on_esc(true);
@@ -41,18 +209,61 @@ fn main() {
     let mut gl = GlGraphics::new(opengl);
 
     let mut galaxy_controller = GalaxyController::from_config(&config);
-    let galaxy_view = GalaxyView::from_config(&config);
+    let mut galaxy_view = GalaxyView::from_config(&config);
+    let mut capture = Capture::from_config(&config);
+    let mut config_watcher = ConfigWatcher::new(args.config.clone(), overrides);
+    let mut clock = SimulationClock::from_config(&config);
+    galaxy_controller.paused = args.paused;
+
+    let exit_after_screenshot = args.screenshot.is_some() || args.svg.is_some();
+    if let Some(path) = args.screenshot {
+        capture.request_screenshot_to(path);
+    }
+    if let Some(path) = args.svg {
+        capture.request_svg_export_to(path);
+    }
+    if args.record {
+        capture.toggle_recording();
+    }
 
     while let Some(e) = events.next(&mut window) {
         galaxy_controller.event(&e);
+        galaxy_view.event(&e);
+        capture.event(&e);
         if let Some(_args) = e.update_args() {
-            galaxy_controller.update(config.frame_time_step);
+            if let Some(new_config) = config_watcher.poll() {
+                galaxy_controller.apply_config(&new_config, &config);
+                galaxy_view.apply_config(&new_config);
+                capture.svg = SvgExporter::from_config(&new_config);
+                clock.apply_config(&new_config);
+                config = new_config;
+            }
         }
         if let Some(args) = e.render_args() {
+            // fixed physics steps follow wall-clock time, planets are drawn in between the last two states
+            if galaxy_controller.paused {
+                clock.pause();
+            } else {
+                for _ in 0..clock.tick() {
+                    galaxy_controller.update(config.frame_time_step);
+                    galaxy_view.hud.ups.tick();
+                    if let Some(error) = &galaxy_controller.error {
+                        println!("simulation paused, {}", error);
+                        break;
+                    }
+                }
+            }
+            galaxy_controller.render_alpha = clock.alpha();
+            let mut view_size = [0.; 2];
             gl.draw(args.viewport(), |c, g| {
                 clear(config.background_color, g);
                 galaxy_view.draw(&galaxy_controller, &c, g);
+                view_size = c.get_view_size();
             });
+            capture.after_render(&galaxy_controller, view_size, args.viewport().draw_size);
+            if exit_after_screenshot && !capture.screenshot_pending() {
+                break;
+            }
         }
     }
 
@@ -0,0 +1,234 @@
+//! Gravitational potential field: heatmap and iso-contours
+
+use graphics::{Context, Image, ImageSize, Line};
+use graphics::math::Scalar;
+use image::{Rgba, RgbaImage};
+use opengl_graphics::{GlGraphics, Texture};
+use piston_window::TextureSettings;
+
+use crate::colormap::Colormap;
+use crate::config::Config;
+use crate::galaxy_controller::GalaxyController;
+
+/// contour lines color
+const CONTOUR_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.6];
+/// contour lines radius (pixels)
+const CONTOUR_RADIUS: Scalar = 0.5;
+
+/// Potential field sampled on a screen-space grid
+pub struct PotentialField {
+    /// draw the heatmap
+    pub visible: bool,
+    /// draw the iso-contours
+    pub contours: bool,
+    /// grid cell size (pixels)
+    pub cell_size: Scalar,
+    /// the field is sampled again every `refresh_interval` frames
+    pub refresh_interval: u32,
+    /// number of iso-contours
+    pub contour_levels: usize,
+    /// frames since last sampling
+    frames: u32,
+    /// sampled potential, row major
+    grid: Vec<Scalar>,
+    /// grid size (cells)
+    grid_size: [usize; 2],
+    /// iso-contour segments (pixels)
+    segments: Vec<[Scalar; 4]>,
+    /// heatmap
+    texture: Option<Texture>,
+}
+
+impl PotentialField {
+    /// Creates a new potential field from config.
+    pub fn from_config(config: &Config) -> PotentialField {
+        PotentialField {
+            visible: config.show_potential,
+            contours: config.show_potential_contours,
+            cell_size: config.potential_cell_size,
+            refresh_interval: config.potential_refresh_interval,
+            contour_levels: config.potential_contour_levels,
+            frames: 0,
+            grid: vec![],
+            grid_size: [0, 0],
+            segments: vec![],
+            texture: None,
+        }
+    }
+
+    /// Draw the heatmap and/or iso-contours, sampling the field again when needed.
+    pub fn draw(&mut self, controller: &GalaxyController, colormap: Colormap, c: &Context, g: &mut GlGraphics) {
+        if !self.visible && !self.contours {
+            self.frames = 0;
+            return;
+        }
+        let view_size = c.get_view_size();
+        let grid_size = [
+            (view_size[0] / self.cell_size).ceil() as usize + 1,
+            (view_size[1] / self.cell_size).ceil() as usize + 1,
+        ];
+        if self.frames == 0 || grid_size != self.grid_size {
+            self.sample(controller, grid_size);
+            self.update_texture(colormap);
+            self.update_contours();
+        }
+        self.frames = (self.frames + 1) % self.refresh_interval.max(1);
+
+        if self.visible {
+            if let Some(texture) = &self.texture {
+                // grid nodes are sampled: texels are centered on them
+                let half_cell = self.cell_size / 2.;
+                let rect = [-half_cell, -half_cell, grid_size[0] as Scalar * self.cell_size, grid_size[1] as Scalar * self.cell_size];
+                Image::new().rect(rect).draw(texture, &c.draw_state, c.transform, g);
+            }
+        }
+        if self.contours {
+            let line = Line::new(CONTOUR_COLOR, CONTOUR_RADIUS);
+            for segment in self.segments.iter() {
+                line.draw(*segment, &c.draw_state, c.transform, g);
+            }
+        }
+    }
+
+    /// Forces the field to be sampled again on next draw.
+    pub fn invalidate(&mut self) {
+        self.frames = 0;
+    }
+
+    fn sample(&mut self, controller: &GalaxyController, grid_size: [usize; 2]) {
+        self.grid_size = grid_size;
+        self.grid.clear();
+        for j in 0..grid_size[1] {
+            for i in 0..grid_size[0] {
+                let view_position = [i as Scalar * self.cell_size, j as Scalar * self.cell_size];
+                let position = controller.camera.view_to_world_position(view_position);
+                self.grid.push(controller.potential_at(position));
+            }
+        }
+    }
+
+    /// the potential spans several orders of magnitude: levels are spread in log(-potential)
+    fn log_range(&self) -> Option<(Scalar, Scalar)> {
+        self.grid.iter().filter(|v| **v < 0.).fold(None, |range, v| {
+            let v = (-v).log10();
+            match range {
+                None => Some((v, v)),
+                Some((min, max)) => Some((min.min(v), max.max(v))),
+            }
+        })
+    }
+
+    fn update_texture(&mut self, colormap: Colormap) {
+        let (min, max) = self.log_range().unwrap_or((0., 0.));
+        let mut image = RgbaImage::new(self.grid_size[0] as u32, self.grid_size[1] as u32);
+        for (index, potential) in self.grid.iter().enumerate() {
+            // deep wells (high -potential) are at the top of the colormap
+            let t = if *potential < 0. && max > min { ((-potential).log10() - min) / (max - min) } else { 0. };
+            let color = colormap.color(t);
+            let pixel = Rgba([
+                (color[0] * 255.) as u8,
+                (color[1] * 255.) as u8,
+                (color[2] * 255.) as u8,
+                255,
+            ]);
+            image.put_pixel((index % self.grid_size[0]) as u32, (index / self.grid_size[0]) as u32, pixel);
+        }
+        match &mut self.texture {
+            Some(texture) if texture.get_size() == image.dimensions() => texture.update(&image),
+            _ => self.texture = Some(Texture::from_image(&image, &TextureSettings::new())),
+        }
+    }
+
+    fn update_contours(&mut self) {
+        self.segments.clear();
+        let (min, max) = match self.log_range() {
+            Some(range) => range,
+            None => return,
+        };
+        let levels = self.contour_levels;
+        for level in 1..=levels {
+            let log_level = min + (max - min) * level as Scalar / (levels + 1) as Scalar;
+            let potential_level = -Scalar::powf(10., log_level);
+            for segment in contour_segments(&self.grid, self.grid_size, potential_level) {
+                self.segments.push([
+                    segment[0] * self.cell_size,
+                    segment[1] * self.cell_size,
+                    segment[2] * self.cell_size,
+                    segment[3] * self.cell_size,
+                ]);
+            }
+        }
+    }
+}
+
+/// Marching squares: iso-line segments of a row major grid at level
+/// segments are [x0, y0, x1, y1] in grid coordinates (1.0 == one cell)
+fn contour_segments(grid: &[Scalar], grid_size: [usize; 2], level: Scalar) -> Vec<[Scalar; 4]> {
+    let mut segments = vec![];
+    if grid_size[0] < 2 || grid_size[1] < 2 {
+        return segments;
+    }
+    let value = |i: usize, j: usize| grid[j * grid_size[0] + i];
+    // crossing point on edge a-b, a and b being grid nodes
+    let crossing = |a: [usize; 2], b: [usize; 2]| {
+        let (va, vb) = (value(a[0], a[1]), value(b[0], b[1]));
+        let t = (level - va) / (vb - va);
+        [a[0] as Scalar + t * (b[0] as Scalar - a[0] as Scalar), a[1] as Scalar + t * (b[1] as Scalar - a[1] as Scalar)]
+    };
+    for j in 0..grid_size[1] - 1 {
+        for i in 0..grid_size[0] - 1 {
+            // corners: top left, top right, bottom right, bottom left
+            let corners = [[i, j], [i + 1, j], [i + 1, j + 1], [i, j + 1]];
+            let above: Vec<bool> = corners.iter().map(|c| value(c[0], c[1]) >= level).collect();
+            // edges: top, right, bottom, left
+            let edges: Vec<Option<[Scalar; 2]>> = (0..4).map(|e| {
+                let (a, b) = (e, (e + 1) % 4);
+                if above[a] != above[b] { Some(crossing(corners[a], corners[b])) } else { None }
+            }).collect();
+            let crossings: Vec<usize> = (0..4).filter(|e| edges[*e].is_some()).collect();
+            let pairs = match crossings.len() {
+                2 => vec![(crossings[0], crossings[1])],
+                4 => {
+                    // saddle: disambiguated with the cell center value
+                    let center = (0..4).map(|k| value(corners[k][0], corners[k][1])).sum::<Scalar>() / 4.;
+                    if (center >= level) == above[0] {
+                        // top left and bottom right are connected, isolate the two other corners
+                        vec![(0, 1), (2, 3)]
+                    } else {
+                        vec![(3, 0), (1, 2)]
+                    }
+                }
+                _ => vec![],
+            };
+            for (a, b) in pairs {
+                if let (Some(pa), Some(pb)) = (edges[a], edges[b]) {
+                    segments.push([pa[0], pa[1], pb[0], pb[1]]);
+                }
+            }
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contour_segments_circle() {
+        // distance to grid center: the level 3.0 iso-line is a circle of radius 3
+        let size = [11, 11];
+        let grid: Vec<Scalar> = (0..size[0] * size[1]).map(|k| {
+            let (x, y) = ((k % size[0]) as Scalar - 5., (k / size[0]) as Scalar - 5.);
+            (x * x + y * y).sqrt()
+        }).collect();
+        let segments = contour_segments(&grid, size, 3.);
+        assert!(!segments.is_empty());
+        for s in segments.iter() {
+            for p in [[s[0], s[1]], [s[2], s[3]]].iter() {
+                let r = ((p[0] - 5.).powi(2) + (p[1] - 5.).powi(2)).sqrt();
+                assert!((r - 3.).abs() < 0.2);
+            }
+        }
+    }
+}
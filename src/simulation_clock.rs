@@ -0,0 +1,101 @@
+//! Simulation clock: fixed time step accumulator driven by wall-clock time
+
+use std::time::Instant;
+
+use graphics::math::Scalar;
+
+use crate::config::Config;
+
+/// Converts elapsed wall-clock time into a number of fixed physics steps
+pub struct SimulationClock {
+    /// fixed physics step (simulated time)
+    pub time_step: Scalar,
+    /// simulated time per wall-clock second
+    pub speed: Scalar,
+    /// steps per tick at most: when physics falls behind, the simulation slows down instead of stalling
+    pub max_steps: u32,
+    /// simulated time due but not simulated yet, < time_step between ticks
+    accumulator: Scalar,
+    /// last tick, None right after creation or pause
+    last_tick: Option<Instant>,
+}
+
+impl SimulationClock {
+    /// Creates a new simulation clock from config.
+    pub fn from_config(config: &Config) -> SimulationClock {
+        SimulationClock {
+            time_step: config.frame_time_step,
+            speed: config.simulation_speed,
+            max_steps: config.max_steps_per_frame,
+            accumulator: 0.,
+            last_tick: None,
+        }
+    }
+
+    /// Applies a (reloaded) config, the accumulated time is kept.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.time_step = config.frame_time_step;
+        self.speed = config.simulation_speed;
+        self.max_steps = config.max_steps_per_frame;
+    }
+
+    /// Number of steps due since the last tick (wall-clock time).
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        let steps = match self.last_tick {
+            Some(last_tick) => {
+                let elapsed = now.duration_since(last_tick);
+                self.advance(elapsed.as_secs() as Scalar + Scalar::from(elapsed.subsec_nanos()) * 1e-9)
+            }
+            None => 0,
+        };
+        self.last_tick = Some(now);
+        steps
+    }
+
+    /// Number of steps due after wall_dt more seconds.
+    pub fn advance(&mut self, wall_dt: Scalar) -> u32 {
+        self.accumulator += wall_dt * self.speed;
+        let steps = (self.accumulator / self.time_step).floor();
+        if steps > Scalar::from(self.max_steps) {
+            // the time physics could not keep up with is dropped
+            self.accumulator = 0.;
+            return self.max_steps;
+        }
+        self.accumulator -= steps * self.time_step;
+        steps as u32
+    }
+
+    /// Stops accumulating time until the next tick (e.g. while paused), alpha is kept.
+    pub fn pause(&mut self) {
+        self.last_tick = None;
+    }
+
+    /// Render interpolation factor in [0, 1] between the last two physics states.
+    pub fn alpha(&self) -> Scalar {
+        (self.accumulator / self.time_step).clamp(0., 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_steps_follow_wall_clock() {
+        let mut config = Config::default();
+        config.frame_time_step = 0.1;
+        config.simulation_speed = 1.;
+        config.max_steps_per_frame = 5;
+        let mut clock = SimulationClock::from_config(&config);
+        // 1.05s in 30 frames: 10 steps of 0.1, whatever the frame rate
+        let steps: u32 = (0..30).map(|_| clock.advance(0.035)).sum();
+        assert_eq!(steps, 10);
+        assert!((clock.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(clock.advance(0.03), 0);
+        assert!((clock.alpha() - 0.8).abs() < 1e-6);
+        // falling behind: at most max_steps, the backlog is dropped
+        assert_eq!(clock.advance(10.), 5);
+        assert_eq!(clock.alpha(), 0.);
+    }
+}
@@ -0,0 +1,127 @@
+//! Softening kernels: gravity between planets closer than the softening length
+//! each force comes with its potential so that energy diagnostics match the simulated force
+
+use graphics::math::Scalar;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// cubic spline kernel support / softening length: same potential at distance 0 as Plummer (GADGET)
+const SPLINE_SUPPORT: Scalar = 2.8;
+
+/// force law between close planets, the softening length is sqrt(softening_factor)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SofteningKernel {
+    /// Newtonian force G.m / d^2, unbounded at close range (coincident planets do not interact)
+    None,
+    /// G.m.d / (d^2 + e^2)^(3/2): a planet behaves like a Plummer sphere of scale e
+    Plummer,
+    /// cubic spline (GADGET): smooth within h = 2.8 e, exactly Newtonian beyond h
+    CubicSpline,
+}
+
+impl SofteningKernel {
+    /// kernel name
+    pub fn name(self) -> &'static str {
+        match self {
+            SofteningKernel::None => "none",
+            SofteningKernel::Plummer => "plummer",
+            SofteningKernel::CubicSpline => "cubic_spline",
+        }
+    }
+
+    /// acceleration (divided by G.m) towards a planet at (dx, dy) is force_factor * (dx, dy)
+    /// finite at distance 0 so that a planet at position (e.g. the planet itself) does not contribute
+    #[inline(always)]
+    pub fn force_factor(self, sq_distance: Scalar, softening_factor: Scalar) -> Scalar {
+        match self {
+            SofteningKernel::None => {
+                if sq_distance > 0. {
+                    1. / (sq_distance * sq_distance.sqrt())
+                } else {
+                    0.
+                }
+            }
+            SofteningKernel::Plummer => {
+                let sq_softened = sq_distance + softening_factor;
+                1. / (sq_softened * sq_softened.sqrt())
+            }
+            SofteningKernel::CubicSpline => {
+                let h = SPLINE_SUPPORT * softening_factor.sqrt();
+                let sq_h = h * h;
+                if sq_distance >= sq_h {
+                    return 1. / (sq_distance * sq_distance.sqrt());
+                }
+                let u = (sq_distance / sq_h).sqrt();
+                let w = if u < 0.5 {
+                    32. / 3. + u * u * (32. * u - 38.4)
+                } else {
+                    64. / 3. - 48. * u + 38.4 * u * u - 32. / 3. * u * u * u - 1. / (15. * u * u * u)
+                };
+                w / (sq_h * h)
+            }
+        }
+    }
+
+    /// potential (divided by G.m) at distance from a planet, its gradient is the force above
+    /// 0 at distance 0 without softening, like the force
+    pub fn potential(self, distance: Scalar, softening_factor: Scalar) -> Scalar {
+        match self {
+            SofteningKernel::None => {
+                if distance > 0. {
+                    -1. / distance
+                } else {
+                    0.
+                }
+            }
+            SofteningKernel::Plummer => -1. / (distance * distance + softening_factor).sqrt(),
+            SofteningKernel::CubicSpline => {
+                let h = SPLINE_SUPPORT * softening_factor.sqrt();
+                if distance >= h {
+                    return -1. / distance;
+                }
+                let u = distance / h;
+                let w = if u < 0.5 {
+                    -2.8 + u * u * (16. / 3. + u * u * (6.4 * u - 9.6))
+                } else {
+                    -3.2 + 1. / (15. * u) + u * u * (32. / 3. + u * (-16. + u * (9.6 - 32. / 15. * u)))
+                };
+                w / h
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [SofteningKernel; 3] = [SofteningKernel::None, SofteningKernel::Plummer, SofteningKernel::CubicSpline];
+
+    #[test]
+    fn test_force_is_potential_gradient() {
+        let softening_factor = 0.25;
+        let h = 1e-5;
+        for kernel in KERNELS.iter() {
+            // inside and outside the spline support (1.4), across its u = 1/2 boundary
+            for distance in [0.1, 0.5, 0.69, 0.71, 1.2, 1.39, 1.41, 3.].iter() {
+                let force = kernel.force_factor(distance * distance, softening_factor) * distance;
+                let gradient = (kernel.potential(distance + h, softening_factor) - kernel.potential(distance - h, softening_factor)) / (2. * h);
+                assert!((force - gradient).abs() < 1e-6 * force, "{}: {} != {} at {}", kernel.name(), force, gradient, distance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels_are_newtonian_far_away() {
+        let softening_factor = 0.01;
+        for kernel in KERNELS.iter() {
+            assert!((kernel.force_factor(100., softening_factor) - 1e-3).abs() < 1e-6);
+            assert!((kernel.potential(10., softening_factor) + 0.1).abs() < 1e-4);
+            assert_eq!(kernel.force_factor(0., softening_factor) * 0., 0.);
+        }
+        // same central potential: -1 / e
+        assert!((SofteningKernel::CubicSpline.potential(0., softening_factor) + 10.).abs() < 1e-9);
+        assert!((SofteningKernel::Plummer.potential(0., softening_factor) + 10.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,212 @@
+//! Software renderer: draws the galaxy into a CPU image buffer (no OpenGL)
+//! e.g. headless runs writing PNG frames or an animated GIF
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gif::SetParameter;
+use graphics::math::Scalar;
+use graphics::types::Color;
+use image::{Rgba, RgbaImage};
+
+use crate::colormap::{to_rgba8, PlanetColoring};
+use crate::config::Config;
+use crate::galaxy_controller::GalaxyController;
+
+/// animated GIF frame delay (1/100 s)
+const GIF_FRAME_DELAY: u16 = 4;
+/// GIF color quantization speed: 1 (best) to 30 (fastest)
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// Draws the same scene as `GalaxyView::draw` planets: background, discs or textures, camera transform
+pub struct SoftwareRenderer {
+    /// image size (pixels)
+    pub size: [u32; 2],
+    /// background color
+    background_color: Color,
+    /// planet color
+    planet_color: Color,
+    /// planet texture
+    planet_texture: Option<RgbaImage>,
+    /// planet coloring by physical quantity
+    coloring: PlanetColoring,
+}
+
+impl SoftwareRenderer {
+    /// Creates a new software renderer from config.
+    pub fn from_config(config: &Config) -> SoftwareRenderer {
+        let texture = match &config.planet_texture_path {
+            None => None,
+            Some(path_string) => image::open(path_string).map(|t| t.to_rgba()).ok(),
+        };
+        SoftwareRenderer {
+            size: config.window_size,
+            background_color: config.background_color,
+            planet_color: config.planet_color,
+            planet_texture: texture,
+            coloring: PlanetColoring::from_config(config),
+        }
+    }
+
+    /// Draw galaxy
+    pub fn render(&self, controller: &GalaxyController) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(self.size[0], self.size[1], to_rgba(self.background_color));
+        let camera = &controller.camera;
+        let planets = &controller.galaxy.planets;
+        let colors = self.coloring.colors(controller);
+        for (i, planet) in planets.iter().enumerate() {
+            let center = camera.world_to_view_position(planet.position);
+            let radius = planet.r * camera.zoom;
+            match (&colors, &self.planet_texture) {
+                (Some(colors), _) => fill_disc(&mut image, center, radius, to_rgba(colors[i])),
+                (None, Some(texture)) => draw_texture(&mut image, center, radius, texture),
+                (None, None) => fill_disc(&mut image, center, radius, to_rgba(self.planet_color)),
+            }
+        }
+        image
+    }
+}
+
+/// Writes rendered frames: numbered PNG files or an animated GIF
+pub enum FrameWriter {
+    /// numbered PNG files in a directory
+    Png {
+        /// output directory
+        directory: PathBuf,
+        /// frames written so far
+        frames: u64,
+    },
+    /// animated GIF
+    Gif(gif::Encoder<File>),
+}
+
+impl FrameWriter {
+    /// Creates an animated GIF writer if path ends with .gif, a PNG directory writer otherwise.
+    pub fn create(path: &Path, size: [u32; 2]) -> io::Result<FrameWriter> {
+        match path.extension() {
+            Some(extension) if extension == "gif" => {
+                let file = File::create(path)?;
+                let mut encoder = gif::Encoder::new(file, size[0] as u16, size[1] as u16, &[])?;
+                encoder.set(gif::Repeat::Infinite)?;
+                Ok(FrameWriter::Gif(encoder))
+            }
+            _ => {
+                fs::create_dir_all(path)?;
+                Ok(FrameWriter::Png { directory: path.to_path_buf(), frames: 0 })
+            }
+        }
+    }
+
+    /// Writes one frame.
+    pub fn write(&mut self, image: &RgbaImage) -> io::Result<()> {
+        match self {
+            FrameWriter::Png { directory, frames } => {
+                image.save(directory.join(format!("frame-{:06}.png", frames)))?;
+                *frames += 1;
+                Ok(())
+            }
+            FrameWriter::Gif(encoder) => {
+                let mut pixels = image.clone().into_raw();
+                let mut frame = gif::Frame::from_rgba_speed(image.width() as u16, image.height() as u16, &mut pixels, GIF_QUANTIZATION_SPEED);
+                frame.delay = GIF_FRAME_DELAY;
+                encoder.write_frame(&frame)
+            }
+        }
+    }
+}
+
+fn to_rgba(color: Color) -> Rgba<u8> {
+    Rgba(to_rgba8(color))
+}
+
+/// source over blending
+fn blend(image: &mut RgbaImage, x: u32, y: u32, source: Rgba<u8>) {
+    let destination = image.get_pixel_mut(x, y);
+    let alpha = Scalar::from(source[3]) / 255.;
+    for k in 0..3 {
+        let channel = Scalar::from(source[k]) * alpha + Scalar::from(destination[k]) * (1. - alpha);
+        destination[k] = channel.round() as u8;
+    }
+    let destination_alpha = Scalar::from(destination[3]) / 255.;
+    destination[3] = ((alpha + destination_alpha * (1. - alpha)) * 255.).round() as u8;
+}
+
+/// pixels whose center lies in the square [center - radius, center + radius]
+fn pixel_range(center: Scalar, radius: Scalar, size: u32) -> std::ops::Range<u32> {
+    let min = (center - radius - 0.5).ceil().max(0.);
+    let max = (center + radius - 0.5).floor() + 1.;
+    let max = max.min(Scalar::from(size)).max(min);
+    (min as u32)..(max as u32)
+}
+
+fn fill_disc(image: &mut RgbaImage, center: [Scalar; 2], radius: Scalar, color: Rgba<u8>) {
+    let (width, height) = image.dimensions();
+    for y in pixel_range(center[1], radius, height) {
+        for x in pixel_range(center[0], radius, width) {
+            let (dx, dy) = (Scalar::from(x) + 0.5 - center[0], Scalar::from(y) + 0.5 - center[1]);
+            if dx * dx + dy * dy <= radius * radius {
+                blend(image, x, y, color);
+            }
+        }
+    }
+}
+
+/// texture stretched over the disc bounding square (nearest sampling), like `Image::rect`
+fn draw_texture(image: &mut RgbaImage, center: [Scalar; 2], radius: Scalar, texture: &RgbaImage) {
+    let (width, height) = image.dimensions();
+    let (texture_width, texture_height) = texture.dimensions();
+    if radius <= 0. || texture_width == 0 || texture_height == 0 {
+        return;
+    }
+    for y in pixel_range(center[1], radius, height) {
+        for x in pixel_range(center[0], radius, width) {
+            let u = (Scalar::from(x) + 0.5 - (center[0] - radius)) / (2. * radius);
+            let v = (Scalar::from(y) + 0.5 - (center[1] - radius)) / (2. * radius);
+            let tx = ((u * Scalar::from(texture_width)) as u32).min(texture_width - 1);
+            let ty = ((v * Scalar::from(texture_height)) as u32).min(texture_height - 1);
+            blend(image, x, y, *texture.get_pixel(tx, ty));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+        let mut config = Config::default();
+        config.window_size = [128, 128];
+        config.camera_position = [-64., -10.];
+        config
+    }
+
+    #[test]
+    fn test_render_pixels() {
+        let config = test_config();
+        let renderer = SoftwareRenderer::from_config(&config);
+        let image = renderer.render(&GalaxyController::from_config(&config));
+        assert_eq!(image.dimensions(), (128, 128));
+        // planet (0, 80) r=20 is drawn at (64, 90)
+        assert_eq!(*image.get_pixel(64, 90), to_rgba(config.planet_color));
+        assert_eq!(*image.get_pixel(0, 0), to_rgba(config.background_color));
+        assert_eq!(*image.get_pixel(64 + 21, 90), to_rgba(config.background_color));
+    }
+
+    #[test]
+    fn test_render_golden_image() {
+        let config = test_config();
+        let renderer = SoftwareRenderer::from_config(&config);
+        let image = renderer.render(&GalaxyController::from_config(&config));
+        // UPDATE_GOLDEN=1 cargo test: accept the current rendering
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            image.save(concat!(env!("CARGO_MANIFEST_DIR"), "/res/golden/default_scene.png")).expect("could not save golden image");
+            return;
+        }
+        let golden = image::load_from_memory(include_bytes!("../res/golden/default_scene.png"))
+            .expect("golden image should be a valid PNG")
+            .to_rgba();
+        assert_eq!(image.dimensions(), golden.dimensions());
+        assert!(image.into_raw() == golden.into_raw(), "rendering differs from res/golden/default_scene.png");
+    }
+}
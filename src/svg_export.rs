@@ -0,0 +1,144 @@
+//! SVG export: vector figure of the current scene (planets, trails, velocities)
+//! shapes are in world coordinates, the viewBox matches what the camera sees
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use graphics::math::{add, mul_scalar, Scalar, Vec2d};
+use graphics::types::Color;
+
+use crate::colormap::{to_rgba8, PlanetColoring};
+use crate::config::Config;
+use crate::galaxy_controller::GalaxyController;
+use crate::galaxy_view::VELOCITY_COLOR;
+
+/// trails opacity relative to their planet color
+const TRAIL_OPACITY: f32 = 0.5;
+/// trail and arrow stroke width (pixels, does not scale with zoom)
+const STROKE_WIDTH: Scalar = 1.;
+
+/// Writes the current galaxy as an SVG document
+pub struct SvgExporter {
+    /// draw planet trails as polylines
+    pub trails: bool,
+    /// draw velocity arrows
+    pub velocities: bool,
+    /// velocity arrow length = velocity * velocity_scale (world units)
+    pub velocity_scale: Scalar,
+    /// background color
+    background_color: Color,
+    /// planet color
+    planet_color: Color,
+    /// planet coloring by physical quantity
+    coloring: PlanetColoring,
+}
+
+impl SvgExporter {
+    /// Creates a new SVG exporter from config.
+    pub fn from_config(config: &Config) -> SvgExporter {
+        SvgExporter {
+            trails: config.svg_trails,
+            velocities: config.svg_velocities,
+            velocity_scale: config.velocity_scale,
+            background_color: config.background_color,
+            planet_color: config.planet_color,
+            coloring: PlanetColoring::from_config(config),
+        }
+    }
+
+    /// SVG document of the galaxy as seen by the camera in a view of view_size (pixels).
+    pub fn to_svg(&self, controller: &GalaxyController, view_size: [Scalar; 2]) -> String {
+        let camera = &controller.camera;
+        let planets = &controller.galaxy.planets;
+        let top_left = camera.view_to_world_position([0., 0.]);
+        let bottom_right = camera.view_to_world_position(view_size);
+        let (width, height) = (bottom_right[0] - top_left[0], bottom_right[1] - top_left[1]);
+        let colors = self.coloring.colors(controller);
+        let planet_color = |i: usize| colors.as_ref().map_or(self.planet_color, |colors| colors[i]);
+
+        let mut svg = String::new();
+        // writing to a String cannot fail
+        writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).ok();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+            view_size[0], view_size[1], top_left[0], top_left[1], width, height
+        ).ok();
+        writeln!(
+            svg,
+            r#"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="4" markerHeight="4" orient="auto"><path d="M 0 0 L 10 5 L 0 10 z" fill="{}"/></marker></defs>"#,
+            rgb(VELOCITY_COLOR)
+        ).ok();
+        writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#, top_left[0], top_left[1], width, height, fill(self.background_color)).ok();
+
+        if self.trails {
+            for (i, trail) in controller.trails.iter().enumerate().filter(|(i, trail)| *i < planets.len() && trail.len() > 1) {
+                let points: Vec<String> = trail.iter().map(|p| format!("{},{}", p[0], p[1])).collect();
+                let color = planet_color(i);
+                writeln!(
+                    svg,
+                    r#"<polyline points="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{}" vector-effect="non-scaling-stroke"/>"#,
+                    points.join(" "), rgb(color), color[3] * TRAIL_OPACITY, STROKE_WIDTH
+                ).ok();
+            }
+        }
+        for (i, planet) in planets.iter().enumerate() {
+            writeln!(svg, r#"<circle cx="{}" cy="{}" r="{}" {}/>"#, planet.position[0], planet.position[1], planet.r, fill(planet_color(i))).ok();
+        }
+        if self.velocities {
+            for planet in planets.iter() {
+                let to: Vec2d<Scalar> = add(planet.position, mul_scalar(planet.velocity, self.velocity_scale));
+                writeln!(
+                    svg,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" vector-effect="non-scaling-stroke" marker-end="url(#arrow)"/>"#,
+                    planet.position[0], planet.position[1], to[0], to[1], rgb(VELOCITY_COLOR), STROKE_WIDTH
+                ).ok();
+            }
+        }
+        writeln!(svg, "</svg>").ok();
+        svg
+    }
+
+    /// Writes the SVG document to path.
+    pub fn export(&self, controller: &GalaxyController, view_size: [Scalar; 2], path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_svg(controller, view_size))
+    }
+}
+
+fn rgb(color: Color) -> String {
+    let [r, g, b, _] = to_rgba8(color);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn fill(color: Color) -> String {
+    format!(r#"fill="{}" fill-opacity="{}""#, rgb(color), color[3])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg_view_box_and_shapes() {
+        let mut config = Config::default();
+        config.camera_position = [-64., -10.];
+        config.default_zoom = 2.;
+        config.svg_velocities = true;
+        let mut controller = GalaxyController::from_config(&config);
+        for _ in 0..10 {
+            controller.update(config.frame_time_step);
+        }
+        let svg = SvgExporter::from_config(&config).to_svg(&controller, [128., 64.]);
+        // view = zoom * world - camera position
+        assert!(svg.contains(r#"viewBox="-32 -5 64 32""#));
+        assert_eq!(svg.matches("<circle ").count(), config.planets.len());
+        assert_eq!(svg.matches("<polyline ").count(), config.planets.len());
+        assert_eq!(svg.matches("<line ").count(), config.planets.len());
+        assert!(svg.contains(r##"fill="#ff9900""##));
+    }
+}
@@ -0,0 +1,128 @@
+//! World grid, origin axes and scale bar
+
+use graphics::{Context, Line, Text, Transformed};
+use graphics::math::Scalar;
+use graphics::types::{Color, FontSize};
+use opengl_graphics::{GlGraphics, GlyphCache};
+
+use crate::camera::Camera;
+use crate::config::Config;
+
+/// grid lines radius (pixels)
+const GRID_RADIUS: Scalar = 0.5;
+/// origin axes radius (pixels)
+const AXES_RADIUS: Scalar = 1.;
+/// scale bar radius (pixels)
+const SCALE_BAR_RADIUS: Scalar = 1.;
+/// scale bar margin to the window border (pixels)
+const SCALE_BAR_MARGIN: Scalar = 16.;
+/// scale bar end ticks half height (pixels)
+const SCALE_BAR_TICK: Scalar = 4.;
+
+/// Adaptive world grid: spacing snaps to 1/2/5 x 10^n as you zoom
+pub struct WorldGrid {
+    /// draw the grid, axes and scale bar
+    pub visible: bool,
+    /// grid color, axes and scale bar are drawn opaque
+    pub color: Color,
+    /// minimum grid spacing > 0 (pixels)
+    pub min_spacing: Scalar,
+}
+
+impl WorldGrid {
+    /// Creates a new world grid from config.
+    pub fn from_config(config: &Config) -> WorldGrid {
+        WorldGrid {
+            visible: config.show_grid,
+            color: config.grid_color,
+            min_spacing: config.grid_min_spacing,
+        }
+    }
+
+    /// grid spacing (world units) and its decimal exponent
+    pub fn spacing(&self, camera: &Camera) -> (Scalar, i32) {
+        nice_spacing(self.min_spacing / camera.zoom)
+    }
+
+    /// Draw grid and origin axes (world space).
+    pub fn draw(&self, camera: &Camera, c: &Context, g: &mut GlGraphics) {
+        if !self.visible {
+            return;
+        }
+        let (spacing, _) = self.spacing(camera);
+        let view_size = c.get_view_size();
+        let top_left = camera.view_to_world_position([0., 0.]);
+        let bottom_right = camera.view_to_world_position(view_size);
+        let transform = camera.world_to_view_transform(c.transform);
+        // constant thickness on screen
+        let grid_line = Line::new(self.color, GRID_RADIUS / camera.zoom);
+        let axes_line = Line::new(opaque(self.color), AXES_RADIUS / camera.zoom);
+
+        let mut x = (top_left[0] / spacing).floor() * spacing;
+        while x <= bottom_right[0] {
+            grid_line.draw([x, top_left[1], x, bottom_right[1]], &c.draw_state, transform, g);
+            x += spacing;
+        }
+        let mut y = (top_left[1] / spacing).floor() * spacing;
+        while y <= bottom_right[1] {
+            grid_line.draw([top_left[0], y, bottom_right[0], y], &c.draw_state, transform, g);
+            y += spacing;
+        }
+        axes_line.draw([top_left[0], 0., bottom_right[0], 0.], &c.draw_state, transform, g);
+        axes_line.draw([0., top_left[1], 0., bottom_right[1]], &c.draw_state, transform, g);
+    }
+
+    /// Draw the scale bar (screen space, bottom left corner).
+    pub fn draw_scale_bar(&self, camera: &Camera, glyphs: Option<&mut GlyphCache<'static>>, font_size: FontSize, c: &Context, g: &mut GlGraphics) {
+        if !self.visible {
+            return;
+        }
+        let (spacing, exponent) = self.spacing(camera);
+        let length = spacing * camera.zoom;
+        let view_size = c.get_view_size();
+        let (x, y) = (SCALE_BAR_MARGIN, view_size[1] - SCALE_BAR_MARGIN);
+        let line = Line::new(opaque(self.color), SCALE_BAR_RADIUS);
+        line.draw([x, y, x + length, y], &c.draw_state, c.transform, g);
+        line.draw([x, y - SCALE_BAR_TICK, x, y + SCALE_BAR_TICK], &c.draw_state, c.transform, g);
+        line.draw([x + length, y - SCALE_BAR_TICK, x + length, y + SCALE_BAR_TICK], &c.draw_state, c.transform, g);
+        if let Some(glyphs) = glyphs {
+            let label = format!("{:.*}", (-exponent).max(0) as usize, spacing);
+            let transform = c.transform.trans(x, y - SCALE_BAR_TICK * 2.);
+            Text::new_color(opaque(self.color), font_size).draw(&label, glyphs, &c.draw_state, transform, g).ok();
+        }
+    }
+}
+
+fn opaque(color: Color) -> Color {
+    [color[0], color[1], color[2], 1.0]
+}
+
+/// Smallest 1/2/5 x 10^n spacing >= min_spacing, with n.
+fn nice_spacing(min_spacing: Scalar) -> (Scalar, i32) {
+    let exponent = min_spacing.log10().floor() as i32;
+    let base = Scalar::powi(10., exponent);
+    for mantissa in [1., 2., 5.].iter() {
+        if mantissa * base >= min_spacing {
+            return (mantissa * base, exponent);
+        }
+    }
+    (10. * base, exponent + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nice_spacing() {
+        let close = |a: Scalar, b: Scalar| (a - b).abs() < 1e-9 * b;
+        assert!(close(nice_spacing(1.).0, 1.));
+        assert!(close(nice_spacing(1.5).0, 2.));
+        assert!(close(nice_spacing(3.).0, 5.));
+        assert!(close(nice_spacing(7.).0, 10.));
+        assert_eq!(nice_spacing(7.).1, 1);
+        assert!(close(nice_spacing(0.03).0, 0.05));
+        assert_eq!(nice_spacing(0.03).1, -2);
+        assert!(close(nice_spacing(420.).0, 500.));
+    }
+}